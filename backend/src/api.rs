@@ -1,17 +1,39 @@
-use actix_web::{HttpResponse, Responder, delete, get, post, web};
+use actix_web::{HttpResponse, Responder, delete, get, patch, post, web};
+use chrono::Utc;
 use serde_json::json;
+use std::io;
 
+use crate::config::Config;
 use crate::error::*;
+use crate::files::*;
 use crate::job::*;
+use crate::joblog::*;
+use crate::retention::*;
+use crate::sse::*;
 use crate::state::*;
 
 pub fn init_routes(cfg: &mut web::ServiceConfig) {
     cfg.service(status)
         .service(start_recorder)
         .service(stop_recorder)
+        .service(run_recorder_now)
+        .service(pause_recorder)
+        .service(resume_recorder)
         .service(remove_recorder)
+        .service(update_recorder)
+        .service(preview_schedule)
         .service(recorder_status_all)
-        .service(recorder_status_one);
+        .service(recorder_status_one)
+        .service(recorder_logs)
+        .service(recorder_log_stream)
+        .service(list_files)
+        .service(delete_files)
+        .service(delete_file)
+        .service(list_job_logs)
+        .service(download_job_log)
+        .service(receiver_status)
+        .service(receiver_status_all)
+        .service(receiver_status_one);
 }
 
 #[get("/api/")]
@@ -26,10 +48,11 @@ async fn recorder_status_all(state: web::Data<AppState>) -> Result<impl Responde
         map.values().cloned().collect::<Vec<_>>()
     };
 
+    let queue = state.queue_info().await;
     let mut job_infos = Vec::with_capacity(jobs.len());
     for job in jobs {
         let job = job.lock().await;
-        job_infos.push(JobInfo::from(&*job));
+        job_infos.push(JobInfo::from(&*job).with_queue(queue.get(&job.id()).copied()));
     }
 
     Ok(HttpResponse::Ok().json(job_infos))
@@ -48,44 +71,179 @@ async fn recorder_status_one(
     }
     .ok_or(ApiError::JobNotFound)?;
 
-    let job_info = JobInfo::from(&*shared_job.lock().await);
+    let job_info = state.job_info(&shared_job).await;
 
     Ok(HttpResponse::Ok().json(job_info))
 }
 
+#[get("/api/recorder/{job_id}/logs")]
+async fn recorder_logs(
+    path: web::Path<u32>,
+    query: web::Query<LogQuery>,
+    state: web::Data<AppState>,
+) -> Result<impl Responder, ApiError> {
+    let job_id = path.into_inner();
+
+    let shared_job = {
+        let map = state.jobs.lock().await;
+        map.get(&job_id).cloned()
+    }
+    .ok_or(ApiError::JobNotFound)?;
+
+    let page = query.apply(shared_job.lock().await.logs());
+
+    Ok(HttpResponse::Ok().json(page))
+}
+
+#[get("/api/recorder/{job_id}/logs/stream")]
+async fn recorder_log_stream(
+    path: web::Path<u32>,
+    state: web::Data<AppState>,
+) -> Result<impl Responder, ApiError> {
+    let job_id = path.into_inner();
+
+    let shared_job = {
+        let map = state.jobs.lock().await;
+        map.get(&job_id).cloned()
+    }
+    .ok_or(ApiError::JobNotFound)?;
+
+    let (backlog, receiver) = shared_job.lock().await.subscribe_logs();
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(("Cache-Control", "no-cache"))
+        .insert_header(("X-Accel-Buffering", "no")) // Stop nginx from buffering the stream
+        .streaming(log_stream(backlog, receiver)))
+}
+
 #[post("/api/recorder/start")]
 async fn start_recorder(
     payload: web::Json<RecorderSettings>,
+    query: web::Query<StartOptions>,
     state: web::Data<AppState>,
 ) -> Result<impl Responder, ApiError> {
-    const MAX_JOB_SLOTS: usize = 3;
     let settings = payload.into_inner();
 
     // Validate settings
     settings
         .validate()
         .map_err(|e| ApiError::InvalidSettings(e.to_string()))?;
+    check_receiver(&settings, &state.config)?;
+    if !state.accepts_jobs() {
+        return Err(ApiError::ShuttingDown);
+    }
 
-    // Check slots
-    {
-        let map = state.jobs.lock().await;
-        if map.len() >= MAX_JOB_SLOTS {
-            return Err(ApiError::NoAvailableSlots);
+    // Check disk space
+    let config = &state.config;
+    if !has_free_space(&config.recorder.output_dir, config.retention.min_free_bytes) {
+        return Err(ApiError::InsufficientDiskSpace);
+    }
+
+    // Check slots and receiver channels, cron jobs wait for their first fire time and don't
+    // need either yet
+    let waits_for_cron = settings.cron().is_some();
+    let receiver = state.receiver_name(&settings).to_string();
+    let channel_free = waits_for_cron || state.channel_free(&receiver).await;
+    let mut slot_free = state.slot_free(&receiver).await && channel_free;
+    let victim = match waits_for_cron || slot_free {
+        true => None,
+        false => {
+            state
+                .preemption_victim(settings.priority(), &receiver)
+                .await
+        }
+    };
+    if !waits_for_cron && !slot_free && victim.is_none() && !query.queue {
+        return match channel_free {
+            true => Err(ApiError::NoAvailableSlots),
+            false => Err(ApiError::NoFreeChannel),
+        };
+    }
+
+    // Check the schedule against other jobs. Without a free slot the first run was already
+    // handled above by queueing or preempting, so only later collisions count.
+    if !query.allow_conflicts {
+        let now = Utc::now().timestamp() as u64;
+        let first_run = settings.first_run_start(now).unwrap_or(now);
+        let mut conflicts = state.schedule_conflicts(&settings, first_run, None).await;
+        if !waits_for_cron && !slot_free {
+            conflicts.retain(|conflict| conflict.start > now);
+        }
+        if !conflicts.is_empty() {
+            return Err(ApiError::ScheduleConflict(conflicts));
         }
     }
 
     // Create job
     let shared_job = create_job(settings, state.jobs.clone()).await;
+    spawn_log_writer(&*shared_job.lock().await, &config.job_logs);
+
+    // Make room by preempting a lower priority job
+    let mut preempted = None;
+    if let Some(victim) = victim {
+        let job_id = shared_job.lock().await.id();
+        match Job::preempt(victim.clone(), job_id, config).await {
+            Ok(()) => {
+                slot_free = true;
+                preempted = Some(victim);
+            }
+            Err(err) => println!("Error id: m3g7yc5ph0xs9lqa. Error info: {}", err),
+        }
+    }
 
     // Start job
-    Job::start(shared_job.clone()).await?;
+    let started = match (waits_for_cron, slot_free) {
+        (true, _) => Ok(()),
+        (false, true) => Job::start(shared_job.clone(), config).await,
+        (false, false) => {
+            shared_job.lock().await.queue();
+            Ok(())
+        }
+    };
+
+    // The preempted job gets its slot back if it was freed for nothing
+    if let (Err(..), Some(victim)) = (&started, preempted)
+        && let Err(err) = Job::start(victim, config).await
+    {
+        println!("Error id: f2r8kd5wq0ym6jtc. Error info: {}", err);
+    }
+    persist_jobs(&state).await;
+    started?;
 
     // Generate JobInfo
-    let job_info = JobInfo::from(&*shared_job.lock().await);
+    let job_info = state.job_info(&shared_job).await;
 
     Ok(HttpResponse::Ok().json(job_info))
 }
 
+#[post("/api/recorder/preview")]
+async fn preview_schedule(
+    payload: web::Json<RecorderSettings>,
+    state: web::Data<AppState>,
+) -> Result<impl Responder, ApiError> {
+    let settings = payload.into_inner();
+
+    settings
+        .validate()
+        .map_err(|e| ApiError::InvalidSettings(e.to_string()))?;
+    check_receiver(&settings, &state.config)?;
+
+    let now = Utc::now().timestamp() as u64;
+    let upcoming_runs = match settings.first_run_start(now) {
+        Some(first) if settings.is_recurring() => settings.upcoming_runs(first, UPCOMING_RUN_COUNT),
+        Some(first) => vec![first],
+        None => Vec::new(),
+    };
+
+    let conflicts = match settings.first_run_start(now) {
+        Some(first) => state.schedule_conflicts(&settings, first, None).await,
+        None => Vec::new(),
+    };
+
+    Ok(HttpResponse::Ok().json(json!({ "upcoming_runs": upcoming_runs, "conflicts": conflicts })))
+}
+
 #[post("/api/recorder/stop/{job_id}")]
 async fn stop_recorder(
     path: web::Path<u32>,
@@ -99,9 +257,183 @@ async fn stop_recorder(
     }
     .ok_or(ApiError::JobNotFound)?;
 
-    Job::stop(shared_job.clone()).await?;
+    Job::stop(shared_job.clone(), &state.config).await?;
+    persist_jobs(&state).await;
+
+    let job_info = state.job_info(&shared_job).await;
+
+    Ok(HttpResponse::Ok().json(job_info))
+}
+
+#[patch("/api/recorder/{job_id}")]
+async fn update_recorder(
+    path: web::Path<u32>,
+    payload: web::Json<SettingsUpdate>,
+    state: web::Data<AppState>,
+) -> Result<impl Responder, ApiError> {
+    let job_id = path.into_inner();
+    let update = payload.into_inner();
+
+    update
+        .settings
+        .validate()
+        .map_err(|e| ApiError::InvalidSettings(e.to_string()))?;
+    check_receiver(&update.settings, &state.config)?;
+
+    let shared_job = {
+        let map = state.jobs.lock().await;
+        map.get(&job_id).cloned()
+    }
+    .ok_or(ApiError::JobNotFound)?;
+
+    // A job that moves to another receiver or changes its schedule has to fit in like a new one
+    let now = Utc::now().timestamp() as u64;
+    let (current, restarts, next_run) = {
+        let job = shared_job.lock().await;
+        let restarts = job.status() == JobStatus::Running && update.apply == ApplyMode::Now;
+        let next_run = match restarts {
+            true => Some(now),
+            false => job.next_run_with(&update.settings, now),
+        };
+        (job.settings().clone(), restarts, next_run)
+    };
+    if restarts && !state.accepts_jobs() {
+        return Err(ApiError::ShuttingDown);
+    }
+    let receiver = state.receiver_name(&update.settings).to_string();
+    let moves = receiver != state.receiver_name(&current);
+    if restarts && moves {
+        if !state.slot_free_for_move(&receiver, job_id).await {
+            return Err(ApiError::NoAvailableSlots);
+        }
+        if !state.channel_free(&receiver).await {
+            return Err(ApiError::NoFreeChannel);
+        }
+    }
+    let rescheduled = moves || !update.settings.same_schedule(&current);
+    if let Some(next_run) = next_run.filter(|_| rescheduled && !update.allow_conflicts) {
+        let conflicts = state
+            .schedule_conflicts(&update.settings, next_run, Some(job_id))
+            .await;
+        if !conflicts.is_empty() {
+            return Err(ApiError::ScheduleConflict(conflicts));
+        }
+    }
+
+    let restart = shared_job
+        .lock()
+        .await
+        .update_settings(update.settings.clone(), update.apply)
+        .map_err(|e| match e.kind() {
+            io::ErrorKind::ResourceBusy => ApiError::JobBusy,
+            _ => ApiError::Process(e),
+        })?;
+
+    let restarted = match restart {
+        true => {
+            Job::restart_with_settings(shared_job.clone(), update.settings, &state.config).await
+        }
+        false => Ok(()),
+    };
+    persist_jobs(&state).await;
+    restarted?;
 
-    let job_info = JobInfo::from(&*shared_job.lock().await);
+    let job_info = state.job_info(&shared_job).await;
+
+    Ok(HttpResponse::Ok().json(job_info))
+}
+
+#[post("/api/recorder/run/{job_id}")]
+async fn run_recorder_now(
+    path: web::Path<u32>,
+    query: web::Query<RunNowOptions>,
+    state: web::Data<AppState>,
+) -> Result<impl Responder, ApiError> {
+    let job_id = path.into_inner();
+
+    let shared_job = {
+        let map = state.jobs.lock().await;
+        map.get(&job_id).cloned()
+    }
+    .ok_or(ApiError::JobNotFound)?;
+
+    if !state.accepts_jobs() {
+        return Err(ApiError::ShuttingDown);
+    }
+    let config = &state.config;
+    if !has_free_space(&config.recorder.output_dir, config.retention.min_free_bytes) {
+        return Err(ApiError::InsufficientDiskSpace);
+    }
+    let receiver = {
+        let job = shared_job.lock().await;
+        state.receiver_name(job.settings()).to_string()
+    };
+    if !state.slot_free(&receiver).await {
+        return Err(ApiError::NoAvailableSlots);
+    }
+    if !state.channel_free(&receiver).await {
+        return Err(ApiError::NoFreeChannel);
+    }
+
+    let started = Job::run_now(shared_job.clone(), query.into_inner(), config).await;
+    persist_jobs(&state).await;
+    started.map_err(|e| match e.kind() {
+        io::ErrorKind::InvalidInput => ApiError::JobNotIdle,
+        _ => ApiError::Process(e),
+    })?;
+
+    let job_info = state.job_info(&shared_job).await;
+
+    Ok(HttpResponse::Ok().json(job_info))
+}
+
+#[post("/api/recorder/pause/{job_id}")]
+async fn pause_recorder(
+    path: web::Path<u32>,
+    state: web::Data<AppState>,
+) -> Result<impl Responder, ApiError> {
+    let job_id = path.into_inner();
+
+    let shared_job = {
+        let map = state.jobs.lock().await;
+        map.get(&job_id).cloned()
+    }
+    .ok_or(ApiError::JobNotFound)?;
+
+    let paused = Job::pause(shared_job.clone(), &state.config).await;
+    persist_jobs(&state).await;
+    paused.map_err(|e| match e.kind() {
+        io::ErrorKind::InvalidInput => ApiError::JobNotRecurring,
+        io::ErrorKind::ResourceBusy => ApiError::JobBusy,
+        _ => ApiError::Process(e),
+    })?;
+
+    let job_info = state.job_info(&shared_job).await;
+
+    Ok(HttpResponse::Ok().json(job_info))
+}
+
+#[post("/api/recorder/resume/{job_id}")]
+async fn resume_recorder(
+    path: web::Path<u32>,
+    state: web::Data<AppState>,
+) -> Result<impl Responder, ApiError> {
+    let job_id = path.into_inner();
+
+    let shared_job = {
+        let map = state.jobs.lock().await;
+        map.get(&job_id).cloned()
+    }
+    .ok_or(ApiError::JobNotFound)?;
+
+    shared_job
+        .lock()
+        .await
+        .resume()
+        .map_err(|_| ApiError::JobNotPaused)?;
+    persist_jobs(&state).await;
+
+    let job_info = state.job_info(&shared_job).await;
 
     Ok(HttpResponse::Ok().json(job_info))
 }
@@ -119,7 +451,159 @@ async fn remove_recorder(
     }
     .ok_or(ApiError::JobNotFound)?;
 
-    Job::stop(shared_job.clone()).await?;
+    persist_jobs(&state).await;
+
+    Job::stop(shared_job.clone(), &state.config).await?;
 
     Ok(HttpResponse::Ok().json(json!({ "message": "Recorder deleted successfully" })))
 }
+
+#[get("/api/files")]
+async fn list_files(
+    query: web::Query<FileQuery>,
+    state: web::Data<AppState>,
+) -> Result<impl Responder, ApiError> {
+    let files = list_recordings(&state.config.recorder.output_dir).await?;
+
+    Ok(HttpResponse::Ok().json(query.apply(files)))
+}
+
+#[delete("/api/files/{name}")]
+async fn delete_file(
+    path: web::Path<String>,
+    state: web::Data<AppState>,
+) -> Result<impl Responder, ApiError> {
+    let name = path.into_inner();
+    let file_path = recording_path(&state.config.recorder.output_dir, &name)
+        .ok_or(ApiError::InvalidFileName)?;
+
+    if is_protected(&name, &state.active_recordings().await) {
+        return Err(ApiError::FileProtected(name));
+    }
+
+    delete_recording(&file_path)
+        .await
+        .map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => ApiError::FileNotFound,
+            io::ErrorKind::InvalidInput => ApiError::FileProtected(name),
+            _ => ApiError::Process(e),
+        })?;
+
+    Ok(HttpResponse::Ok().json(json!({ "message": "File deleted successfully" })))
+}
+
+#[delete("/api/files")]
+async fn delete_files(
+    query: web::Query<FileFilter>,
+    state: web::Data<AppState>,
+) -> Result<impl Responder, ApiError> {
+    let dir = &state.config.recorder.output_dir;
+    let now = Utc::now().timestamp() as u64;
+
+    let files = query
+        .select(list_recordings(dir).await?, now)
+        .map_err(ApiError::InvalidFileFilter)?;
+    let active = state.active_recordings().await;
+
+    let mut deleted = Vec::new();
+    let mut protected = Vec::new();
+    let mut failed = Vec::new();
+    for file in files {
+        let name = file.name().to_string();
+
+        if is_protected(&name, &active) {
+            protected.push(name);
+            continue;
+        }
+
+        match delete_recording(&dir.join(&name)).await {
+            Ok(()) => deleted.push(name),
+            Err(err) => failed.push(json!({ "name": name, "error": err.to_string() })),
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(json!({
+        "deleted": deleted,
+        "protected": protected,
+        "failed": failed,
+    })))
+}
+
+#[get("/api/logs")]
+async fn list_job_logs(state: web::Data<AppState>) -> Result<impl Responder, ApiError> {
+    let files = list_log_files(&state.config.job_logs.dir).await?;
+
+    Ok(HttpResponse::Ok().json(files))
+}
+
+#[get("/api/logs/{name}")]
+async fn download_job_log(
+    path: web::Path<String>,
+    state: web::Data<AppState>,
+) -> Result<impl Responder, ApiError> {
+    let name = path.into_inner();
+    let file_path = recording_path(&state.config.job_logs.dir, &name)
+        .filter(|_| name.ends_with(".log"))
+        .ok_or(ApiError::InvalidFileName)?;
+
+    let data = tokio::fs::read(&file_path)
+        .await
+        .map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => ApiError::FileNotFound,
+            _ => ApiError::Process(e),
+        })?;
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/plain; charset=utf-8")
+        .insert_header((
+            "Content-Disposition",
+            format!("attachment; filename=\"{}\"", name),
+        ))
+        .body(data))
+}
+
+/// Saving is best effort here, the job scheduler retries on its next tick.
+async fn persist_jobs(state: &AppState) {
+    if let Err(err) = state.persist_jobs().await {
+        println!("Error id: 3mcx81hq5tzp0wbe. Error info: {}", err);
+    }
+}
+
+/// Kept from before multiple receivers were supported, answers for the first one.
+#[get("/api/receiver")]
+async fn receiver_status(state: web::Data<AppState>) -> Result<impl Responder, ApiError> {
+    let receiver = state
+        .refresh_first_receiver()
+        .await
+        .map_err(|e| ApiError::ReceiverUnavailable(e.to_string()))?;
+
+    Ok(HttpResponse::Ok().json(receiver))
+}
+
+#[get("/api/receivers")]
+async fn receiver_status_all(state: web::Data<AppState>) -> Result<impl Responder, ApiError> {
+    Ok(HttpResponse::Ok().json(state.receiver_infos().await))
+}
+
+#[get("/api/receivers/{name}")]
+async fn receiver_status_one(
+    path: web::Path<String>,
+    state: web::Data<AppState>,
+) -> Result<impl Responder, ApiError> {
+    let name = path.into_inner();
+
+    let receiver = state
+        .receiver_info(&name)
+        .await
+        .ok_or(ApiError::ReceiverNotFound(name))?;
+
+    Ok(HttpResponse::Ok().json(receiver))
+}
+
+/// Fails if `settings` name a receiver that isn't configured.
+fn check_receiver(settings: &RecorderSettings, config: &Config) -> Result<(), ApiError> {
+    match (settings.receiver(), config.receiver(settings.receiver())) {
+        (Some(name), None) => Err(ApiError::ReceiverNotFound(name.to_string())),
+        _ => Ok(()),
+    }
+}
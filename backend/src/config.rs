@@ -0,0 +1,315 @@
+use crate::joblog::JobLogConfig;
+use crate::kiwi::{DEFAULT_RECEIVER, KiwiReceiver, ReceiverConfig, Secret};
+use crate::retention::RetentionPolicy;
+use crate::store::DEFAULT_JOB_STORE_PATH;
+use clap::Parser;
+use serde::Deserialize;
+use std::io;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+pub const DEFAULT_CONFIG_PATH: &str = "/etc/kiwibackend/config.toml";
+
+#[derive(Parser, Debug)]
+#[command(about = "KiwiSDR recorder job scheduler")]
+pub struct Cli {
+    /// Path to the TOML config file
+    #[arg(short, long, env = "KIWI_BACKEND_CONFIG")]
+    pub config: Option<PathBuf>,
+}
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("Could not read config file {0}: {1}")]
+    Read(PathBuf, io::Error),
+
+    #[error("Invalid config file {0}: {1}")]
+    Parse(PathBuf, toml::de::Error),
+
+    #[error("Invalid config file {0}: receiver {1} is defined more than once")]
+    DuplicateReceiver(PathBuf, String),
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub bind_address: String,
+    pub port: u16,
+    pub max_job_slots: usize,
+    pub conflict_horizon_days: u32, // How far ahead new jobs are checked for slot conflicts, 0 == never
+    pub shutdown_timeout: u64,      // sec the backend takes at most to stop all recordings on exit
+    pub job_store: PathBuf,
+    pub recorder: RecorderConfig,
+    pub retention: RetentionPolicy,
+    pub job_logs: JobLogConfig,
+    pub receiver: ReceiverConfig,
+    pub receivers: Vec<KiwiReceiver>, // Empty == only the Kiwi in `recorder`
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            bind_address: "0.0.0.0".into(),
+            port: 5004,
+            max_job_slots: 3,
+            conflict_horizon_days: 7,
+            shutdown_timeout: 30,
+            job_store: DEFAULT_JOB_STORE_PATH.into(),
+            recorder: RecorderConfig::default(),
+            retention: RetentionPolicy::default(),
+            job_logs: JobLogConfig::default(),
+            receiver: ReceiverConfig::default(),
+            receivers: Vec::new(),
+        }
+    }
+}
+
+/// Everything needed to launch kiwirecorder.py against a receiver.
+#[derive(Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct RecorderConfig {
+    pub kiwi_host: String,
+    pub kiwi_port: u16,
+    pub password: Option<Secret>, // For the Kiwi above, unused with [[receivers]]
+    pub tlimit_password: Option<Secret>, // Same
+    pub python: PathBuf,
+    pub kiwiclient_dir: PathBuf, // Holds kiwirecorder.py
+    pub output_dir: PathBuf,
+    pub stop_grace_period: u64, // sec between SIGINT and SIGKILL when stopping a recording
+}
+
+impl Default for RecorderConfig {
+    fn default() -> Self {
+        Self {
+            kiwi_host: "127.0.0.1".into(),
+            kiwi_port: 8073,
+            password: None,
+            tlimit_password: None,
+            python: "python3".into(),
+            kiwiclient_dir: "/usr/local/src/kiwiclient/".into(),
+            output_dir: "/var/recorder/recorded-files/".into(),
+            stop_grace_period: 10,
+        }
+    }
+}
+
+impl Config {
+    pub fn parse(path: &Path, data: &str) -> Result<Self, ConfigError> {
+        let config: Self =
+            toml::from_str(data).map_err(|e| ConfigError::Parse(path.to_path_buf(), e))?;
+
+        for (i, receiver) in config.receivers.iter().enumerate() {
+            if config.receivers[..i]
+                .iter()
+                .any(|other| other.name == receiver.name)
+            {
+                return Err(ConfigError::DuplicateReceiver(
+                    path.to_path_buf(),
+                    receiver.name.clone(),
+                ));
+            }
+        }
+
+        Ok(config)
+    }
+
+    /// Receiver passwords are kept in the config file, so it shouldn't be readable by others.
+    fn warn_if_exposed(&self, path: &Path) {
+        let recorder = &self.recorder;
+        let recorder_has_credentials =
+            recorder.password.is_some() || recorder.tlimit_password.is_some();
+        if !recorder_has_credentials && !self.receivers.iter().any(KiwiReceiver::has_credentials) {
+            return;
+        }
+
+        match std::fs::metadata(path) {
+            Ok(metadata) if metadata.permissions().mode() & 0o077 != 0 => println!(
+                "Warning: {} holds receiver passwords but is readable by other users, consider chmod 600",
+                path.display()
+            ),
+            _ => {}
+        }
+    }
+
+    /// The receivers jobs can record from, never empty. Without `[[receivers]]` this is the
+    /// Kiwi in `recorder`, named "default", with `max_job_slots` channels.
+    pub fn receivers(&self) -> Vec<KiwiReceiver> {
+        if !self.receivers.is_empty() {
+            return self.receivers.clone();
+        }
+
+        vec![KiwiReceiver {
+            name: DEFAULT_RECEIVER.into(),
+            host: self.recorder.kiwi_host.clone(),
+            port: self.recorder.kiwi_port,
+            password: self.recorder.password.clone(),
+            tlimit_password: self.recorder.tlimit_password.clone(),
+            channels: self.max_job_slots,
+        }]
+    }
+
+    /// Looks up a receiver by name, None == the first one.
+    pub fn receiver(&self, name: Option<&str>) -> Option<KiwiReceiver> {
+        let receivers = self.receivers();
+        match name {
+            Some(name) => receivers.into_iter().find(|receiver| receiver.name == name),
+            None => receivers.into_iter().next(),
+        }
+    }
+
+    /// Loads the config from `path`. Without a path the default location is tried,
+    /// and if nothing is there the built in defaults are used.
+    pub fn load(path: Option<&Path>) -> Result<Self, ConfigError> {
+        let (path, required) = match path {
+            Some(path) => (path, true),
+            None => (Path::new(DEFAULT_CONFIG_PATH), false),
+        };
+
+        match std::fs::read_to_string(path) {
+            Ok(data) => {
+                let config = Self::parse(path, &data)?;
+                config.warn_if_exposed(path);
+                Ok(config)
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound && !required => Ok(Self::default()),
+            Err(err) => Err(ConfigError::Read(path.to_path_buf(), err)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(data: &str) -> Result<Config, ConfigError> {
+        Config::parse(Path::new("test.toml"), data)
+    }
+
+    #[test]
+    fn empty_is_default() {
+        assert_eq!(parse("").unwrap(), Config::default());
+    }
+
+    #[test]
+    fn partial_override() {
+        let config = parse(
+            r#"
+            port = 6000
+            max_job_slots = 8
+
+            [recorder]
+            kiwi_host = "10.0.0.20"
+
+            [retention]
+            max_age = 86400
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.port, 6000);
+        assert_eq!(config.max_job_slots, 8);
+        assert_eq!(config.bind_address, "0.0.0.0");
+        assert_eq!(config.recorder.kiwi_host, "10.0.0.20");
+        assert_eq!(config.recorder.kiwi_port, 8073);
+        assert_eq!(config.retention.max_age, Some(86400));
+        assert_eq!(
+            config.retention.min_free_bytes,
+            RetentionPolicy::default().min_free_bytes
+        );
+    }
+
+    #[test]
+    fn receivers_default_to_recorder_kiwi() {
+        let config = parse("max_job_slots = 2").unwrap();
+        let receivers = config.receivers();
+        assert_eq!(receivers.len(), 1);
+        assert_eq!(receivers[0].name, "default");
+        assert_eq!(receivers[0].host, "127.0.0.1");
+        assert_eq!(receivers[0].channels, 2);
+    }
+
+    #[test]
+    fn default_receiver_takes_recorder_passwords() {
+        let config = parse(
+            r#"
+            [recorder]
+            password = "secret"
+            "#,
+        )
+        .unwrap();
+
+        let receiver = config.receiver(None).unwrap();
+        assert_eq!(receiver.password, Some(Secret::new("secret")));
+        assert!(receiver.tlimit_password.is_none());
+    }
+
+    #[test]
+    fn configured_receivers() {
+        let config = parse(
+            r#"
+            [[receivers]]
+            name = "roof"
+            host = "10.0.0.21"
+
+            [[receivers]]
+            name = "mast"
+            host = "10.0.0.22"
+            port = 8074
+            channels = 8
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.receiver(None).unwrap().name, "roof");
+        let mast = config.receiver(Some("mast")).unwrap();
+        assert_eq!((mast.port, mast.channels), (8074, 8));
+        assert_eq!(config.receiver(Some("roof")).unwrap().port, 8073);
+        assert!(config.receiver(Some("default")).is_none());
+    }
+
+    #[test]
+    fn duplicate_receivers_are_rejected() {
+        let data = r#"
+            [[receivers]]
+            name = "roof"
+            host = "10.0.0.21"
+
+            [[receivers]]
+            name = "roof"
+            host = "10.0.0.22"
+            "#;
+        assert!(matches!(
+            parse(data),
+            Err(ConfigError::DuplicateReceiver(_, name)) if name == "roof"
+        ));
+    }
+
+    #[test]
+    fn example_is_default() {
+        let example = include_str!("../config.example.toml");
+        assert_eq!(parse(example).unwrap(), Config::default());
+    }
+
+    #[test]
+    fn unknown_keys_are_rejected() {
+        assert!(matches!(parse("prot = 6000"), Err(ConfigError::Parse(..))));
+    }
+
+    #[test]
+    fn missing_default_file_is_default() {
+        // The default path doesn't exist in the test environment
+        if !Path::new(DEFAULT_CONFIG_PATH).exists() {
+            assert_eq!(Config::load(None).unwrap(), Config::default());
+        }
+    }
+
+    #[test]
+    fn missing_explicit_file_is_an_error() {
+        let path = std::env::temp_dir().join("kiwi-config-does-not-exist.toml");
+        assert!(matches!(
+            Config::load(Some(&path)),
+            Err(ConfigError::Read(..))
+        ));
+    }
+}
@@ -1,3 +1,4 @@
+use crate::schedule::{ScheduleConflict, describe_conflicts};
 use actix_web::{HttpResponse, ResponseError};
 use serde_json::json;
 use std::io;
@@ -11,6 +12,15 @@ pub enum ApiError {
     #[error("All recorder slots are full")]
     NoAvailableSlots,
 
+    #[error("All receiver channels are in use")]
+    NoFreeChannel,
+
+    #[error("Receiver unavailable: {0}")]
+    ReceiverUnavailable(String),
+
+    #[error("Receiver not found: {0}")]
+    ReceiverNotFound(String),
+
     #[error("Invalid settings: {0}")]
     InvalidSettings(String),
 
@@ -20,25 +30,78 @@ pub enum ApiError {
     #[error("Job is not running")]
     JobNotRunning,
 
+    #[error("Job would need more slots than are available: {}", describe_conflicts(.0))]
+    ScheduleConflict(Vec<ScheduleConflict>),
+
+    #[error("Job is starting or stopping")]
+    JobBusy,
+
+    #[error("Only recurring jobs can be paused")]
+    JobNotRecurring,
+
+    #[error("Job is not paused")]
+    JobNotPaused,
+
+    #[error("File not found")]
+    FileNotFound,
+
+    #[error("Invalid file name")]
+    InvalidFileName,
+
+    #[error("Invalid file filter: {0}")]
+    InvalidFileFilter(String),
+
+    #[error("File is protected: {0}")]
+    FileProtected(String),
+
+    #[error("Not enough free disk space for new recordings")]
+    InsufficientDiskSpace,
+
     #[error("Process error: {0}")]
     Process(#[from] io::Error),
 
+    #[error("The backend is shutting down")]
+    ShuttingDown,
+
     #[error("Internal server error")]
     Internal,
 }
 
 impl ResponseError for ApiError {
     fn error_response(&self) -> HttpResponse {
-        let body = json!({
-            "error": self.to_string()
-        });
+        let body = match self {
+            ApiError::ScheduleConflict(conflicts) => json!({
+                "error": self.to_string(),
+                "conflicts": conflicts
+            }),
+            _ => json!({
+                "error": self.to_string()
+            }),
+        };
 
         match self {
-            ApiError::JobNotFound | ApiError::NoAvailableSlots | ApiError::InvalidSettings(_) => {
-                HttpResponse::BadRequest().json(body)
-            }
+            ApiError::JobNotFound
+            | ApiError::NoAvailableSlots
+            | ApiError::NoFreeChannel
+            | ApiError::ReceiverNotFound(_)
+            | ApiError::InvalidSettings(_)
+            | ApiError::FileNotFound
+            | ApiError::InvalidFileName
+            | ApiError::InvalidFileFilter(_) => HttpResponse::BadRequest().json(body),
+
+            ApiError::JobNotIdle
+            | ApiError::JobNotRunning
+            | ApiError::JobBusy
+            | ApiError::ScheduleConflict(_)
+            | ApiError::JobNotRecurring
+            | ApiError::JobNotPaused
+            | ApiError::FileProtected(_) => HttpResponse::Conflict().json(body),
+
+            ApiError::ReceiverUnavailable(_) => HttpResponse::BadGateway().json(body),
+
+            ApiError::ShuttingDown => HttpResponse::ServiceUnavailable().json(body),
 
-            ApiError::JobNotIdle | ApiError::JobNotRunning => HttpResponse::Conflict().json(body),
+            ApiError::InsufficientDiskSpace => HttpResponse::InsufficientStorage().json(body),
 
             ApiError::Process(_) | ApiError::Internal => {
                 HttpResponse::InternalServerError().json(body)
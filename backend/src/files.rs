@@ -0,0 +1,577 @@
+use crate::job::{Modulation, RecordingType, from_scientific};
+use chrono::NaiveDateTime;
+use glob::Pattern;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use tokio::fs;
+
+/// Prefix kiwirecorder.py puts in front of the station name, see `RecorderSettings::as_args`.
+const FILENAME_PREFIX: &str = "KiwiRec_";
+
+/// What can be read back out of a filename made by `RecorderSettings::get_filename`.
+#[derive(Serialize, Clone, Debug, PartialEq, Eq, Default)]
+pub struct RecordingMetadata {
+    job_uid: String,
+    recorded_at: u64, // Unix
+    frequency: u32,   // Hz, rounded to 4 significant digits by `to_scientific`
+    rec_type: Option<RecordingType>,
+    zoom: Option<u8>,
+    bandwidth: Option<u32>, // Hz
+    modulation: Option<Modulation>,
+}
+
+impl RecordingMetadata {
+    pub fn parse(filename: &str) -> Option<Self> {
+        let stem = filename.split_once('.').map_or(filename, |(stem, _)| stem);
+        let stem = stem.strip_prefix(FILENAME_PREFIX).unwrap_or(stem);
+
+        let mut tokens = stem.split('_');
+        let job_uid = tokens.next()?.to_string();
+        let date = tokens.next()?;
+        let time = tokens.next()?;
+        if tokens.next()? != "UTC" {
+            return None;
+        }
+        let frequency = from_scientific(tokens.next()?.strip_prefix("Fq")?)?;
+
+        let recorded_at =
+            NaiveDateTime::parse_from_str(&format!("{}_{}", date, time), "%Y-%m-%d_%H-%M-%S")
+                .ok()?
+                .and_utc()
+                .timestamp() as u64;
+
+        let mut metadata = Self {
+            job_uid,
+            recorded_at,
+            frequency,
+            ..Default::default()
+        };
+
+        for token in tokens {
+            if let Some(zoom) = token.strip_prefix("Zm") {
+                metadata.zoom = zoom.parse().ok();
+                metadata.rec_type = Some(RecordingType::PNG);
+            } else if let Some(modulation) = token.strip_prefix("Md") {
+                metadata.modulation = Modulation::from_arg(modulation);
+                metadata.rec_type = Some(RecordingType::Audio);
+            } else if let Some(bandwidth) = token.strip_prefix("Bw") {
+                metadata.bandwidth = from_scientific(bandwidth);
+                metadata.rec_type.get_or_insert(RecordingType::IQ);
+            }
+        }
+
+        Some(metadata)
+    }
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct RecordingFile {
+    name: String,
+    size: u64,     // Bytes
+    modified: u64, // Unix
+    extension: String,
+    metadata: Option<RecordingMetadata>, // None if the file wasn't made by the recorder
+}
+
+impl RecordingFile {
+    pub fn new(name: String, size: u64, modified: u64) -> Self {
+        let extension = match name.rsplit_once('.') {
+            Some((_, extension)) => extension.to_lowercase(),
+            None => String::new(),
+        };
+        let metadata = RecordingMetadata::parse(&name);
+
+        Self {
+            name,
+            size,
+            modified,
+            extension,
+            metadata,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// When the recording started, falling back to the modification time for foreign files.
+    pub fn time(&self) -> u64 {
+        self.metadata
+            .as_ref()
+            .map_or(self.modified, |metadata| metadata.recorded_at)
+    }
+
+    pub fn job_uid(&self) -> Option<&str> {
+        self.metadata
+            .as_ref()
+            .map(|metadata| metadata.job_uid.as_str())
+    }
+
+    fn frequency(&self) -> Option<u32> {
+        self.metadata.as_ref().map(|metadata| metadata.frequency)
+    }
+
+    fn rec_type(&self) -> Option<RecordingType> {
+        self.metadata
+            .as_ref()
+            .and_then(|metadata| metadata.rec_type)
+    }
+}
+
+/// Lists all regular files directly inside `dir`. A missing directory has no recordings.
+pub async fn list_recordings(dir: &Path) -> io::Result<Vec<RecordingFile>> {
+    let mut entries = match fs::read_dir(dir).await {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err),
+    };
+
+    let mut files = Vec::new();
+    while let Some(entry) = entries.next_entry().await? {
+        let metadata = entry.metadata().await?;
+        if !metadata.is_file() {
+            continue;
+        }
+
+        let modified = metadata
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_secs());
+
+        files.push(RecordingFile::new(
+            entry.file_name().to_string_lossy().into_owned(),
+            metadata.len(),
+            modified,
+        ));
+    }
+
+    Ok(files)
+}
+
+/// Joins `name` onto `dir`, or returns None if `name` is anything but a plain file name,
+/// so requests can't reach outside of `dir`.
+pub fn recording_path(dir: &Path, name: &str) -> Option<PathBuf> {
+    let is_plain =
+        !name.is_empty() && name != "." && name != ".." && !name.contains(['/', '\\', '\0']);
+
+    is_plain.then(|| dir.join(name))
+}
+
+/// Deletes a single recording. Directories and symlinks are refused.
+pub async fn delete_recording(path: &Path) -> io::Result<()> {
+    let metadata = fs::symlink_metadata(path).await?;
+    if !metadata.is_file() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Not a regular file",
+        ));
+    }
+
+    fs::remove_file(path).await
+}
+
+/// True if `name` is being written by an active job, `active` holds their station names.
+pub fn is_protected(name: &str, active: &[String]) -> bool {
+    active.iter().any(|station| name.contains(station.as_str()))
+}
+
+/// Selects recordings for bulk deletion. All given criteria must match.
+#[derive(Deserialize, Clone, Debug, Default)]
+pub struct FileFilter {
+    job_uid: Option<String>,
+    older_than: Option<u64>, // sec
+    pattern: Option<String>, // Glob matched against the file name
+}
+
+impl FileFilter {
+    pub fn select(
+        &self,
+        files: Vec<RecordingFile>,
+        now: u64,
+    ) -> Result<Vec<RecordingFile>, String> {
+        if self.job_uid.is_none() && self.older_than.is_none() && self.pattern.is_none() {
+            return Err("At least one of job_uid, older_than and pattern is required".into());
+        }
+
+        let pattern = match &self.pattern {
+            Some(pattern) => Some(Pattern::new(pattern).map_err(|e| e.to_string())?),
+            None => None,
+        };
+
+        Ok(files
+            .into_iter()
+            .filter(|file| {
+                self.job_uid
+                    .as_deref()
+                    .is_none_or(|job_uid| file.job_uid() == Some(job_uid))
+                    && self
+                        .older_than
+                        .is_none_or(|age| file.time().saturating_add(age) < now)
+                    && pattern
+                        .as_ref()
+                        .is_none_or(|pattern| pattern.matches(file.name()))
+            })
+            .collect())
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FileSort {
+    #[default]
+    Time,
+    Name,
+    Size,
+    Frequency,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    Asc,
+    #[default]
+    Desc,
+}
+
+#[derive(Deserialize, Clone, Debug, Default)]
+pub struct FileQuery {
+    job_uid: Option<String>,
+    rec_type: Option<RecordingType>,
+    from: Option<u64>, // Unix, inclusive
+    to: Option<u64>,   // Unix, inclusive
+    #[serde(default)]
+    sort: FileSort,
+    #[serde(default)]
+    order: SortOrder,
+    #[serde(default)]
+    offset: usize,
+    limit: Option<usize>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct FilePage {
+    total: usize, // Matching files before pagination
+    offset: usize,
+    limit: usize,
+    files: Vec<RecordingFile>,
+}
+
+impl FileQuery {
+    const DEFAULT_LIMIT: usize = 100;
+    const MAX_LIMIT: usize = 1000;
+
+    fn matches(&self, file: &RecordingFile) -> bool {
+        if let Some(job_uid) = &self.job_uid
+            && file.job_uid() != Some(job_uid.as_str())
+        {
+            return false;
+        }
+        if let Some(rec_type) = self.rec_type
+            && file.rec_type() != Some(rec_type)
+        {
+            return false;
+        }
+
+        let time = file.time();
+        self.from.is_none_or(|from| time >= from) && self.to.is_none_or(|to| time <= to)
+    }
+
+    fn compare(&self, a: &RecordingFile, b: &RecordingFile) -> Ordering {
+        let ordering = match self.sort {
+            FileSort::Time => a.time().cmp(&b.time()),
+            FileSort::Name => a.name.cmp(&b.name),
+            FileSort::Size => a.size.cmp(&b.size),
+            FileSort::Frequency => a.frequency().cmp(&b.frequency()),
+        }
+        .then_with(|| a.name.cmp(&b.name));
+
+        match self.order {
+            SortOrder::Asc => ordering,
+            SortOrder::Desc => ordering.reverse(),
+        }
+    }
+
+    pub fn apply(&self, files: Vec<RecordingFile>) -> FilePage {
+        let limit = self
+            .limit
+            .unwrap_or(Self::DEFAULT_LIMIT)
+            .min(Self::MAX_LIMIT);
+
+        let mut files: Vec<RecordingFile> = files
+            .into_iter()
+            .filter(|file| self.matches(file))
+            .collect();
+        files.sort_by(|a, b| self.compare(a, b));
+
+        FilePage {
+            total: files.len(),
+            offset: self.offset,
+            limit,
+            files: files.into_iter().skip(self.offset).take(limit).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2025-01-01 06:00:00 UTC
+    const SIX_AM: u64 = 1_735_711_200;
+
+    fn png(uid: &str, time: &str, size: u64) -> RecordingFile {
+        RecordingFile::new(
+            format!("KiwiRec_{}_2025-01-01_{}_UTC_Fq1d42e7_Zm10.png", uid, time),
+            size,
+            0,
+        )
+    }
+
+    fn iq(uid: &str, time: &str) -> RecordingFile {
+        RecordingFile::new(
+            format!(
+                "KiwiRec_{}_2025-01-01_{}_UTC_Fq9d475e5_Bw1d2e4.wav",
+                uid, time
+            ),
+            10,
+            0,
+        )
+    }
+
+    #[test]
+    fn parse_png() {
+        let metadata =
+            RecordingMetadata::parse("KiwiRec_ABCD-EFGH_2025-01-01_06-00-00_UTC_Fq1d42e7_Zm10.png")
+                .unwrap();
+
+        assert_eq!(
+            metadata,
+            RecordingMetadata {
+                job_uid: "ABCD-EFGH".into(),
+                recorded_at: SIX_AM,
+                frequency: 14_200_000,
+                rec_type: Some(RecordingType::PNG),
+                zoom: Some(10),
+                bandwidth: None,
+                modulation: None,
+            }
+        );
+    }
+
+    #[test]
+    fn parse_iq() {
+        let metadata = RecordingMetadata::parse(
+            "KiwiRec_ABCD-EFGH_2025-01-01_06-00-00_UTC_Fq9d475e5_Bw1d2e4.wav",
+        )
+        .unwrap();
+
+        assert_eq!(metadata.frequency, 947_500);
+        assert_eq!(metadata.bandwidth, Some(12_000));
+        assert_eq!(metadata.rec_type, Some(RecordingType::IQ));
+    }
+
+    #[test]
+    fn parse_audio() {
+        let metadata = RecordingMetadata::parse(
+            "KiwiRec_ABCD-EFGH_2025-01-01_06-00-00_UTC_Fq1d42e7_Mdusb_Bw2d7e3.wav",
+        )
+        .unwrap();
+
+        assert_eq!(metadata.rec_type, Some(RecordingType::Audio));
+        assert_eq!(metadata.modulation, Some(Modulation::USB));
+        assert_eq!(metadata.bandwidth, Some(2_700));
+    }
+
+    #[test]
+    fn parse_foreign_file() {
+        assert_eq!(RecordingMetadata::parse("notes.txt"), None);
+        assert_eq!(
+            RecordingMetadata::parse("KiwiRec_ABCD-EFGH_yesterday_UTC_Fq1e7.png"),
+            None
+        );
+    }
+
+    #[test]
+    fn foreign_file_uses_modified_time() {
+        let file = RecordingFile::new("notes.txt".into(), 1, 1234);
+        assert_eq!(file.time(), 1234);
+        assert_eq!(file.extension, "txt");
+    }
+
+    #[test]
+    fn default_sort_is_newest_first() {
+        let files = vec![
+            png("AAAA-AAAA", "06-00-00", 1),
+            png("AAAA-AAAA", "08-00-00", 1),
+            png("AAAA-AAAA", "07-00-00", 1),
+        ];
+
+        let page = FileQuery::default().apply(files);
+        let times: Vec<u64> = page.files.iter().map(|file| file.time()).collect();
+        assert_eq!(times, vec![SIX_AM + 7200, SIX_AM + 3600, SIX_AM]);
+    }
+
+    #[test]
+    fn filter_by_job_uid_and_type() {
+        let files = vec![
+            png("AAAA-AAAA", "06-00-00", 1),
+            png("BBBB-BBBB", "06-00-00", 1),
+            iq("AAAA-AAAA", "06-00-00"),
+        ];
+
+        let query = FileQuery {
+            job_uid: Some("AAAA-AAAA".into()),
+            rec_type: Some(RecordingType::PNG),
+            ..Default::default()
+        };
+        let page = query.apply(files);
+
+        assert_eq!(page.total, 1);
+        assert_eq!(page.files[0].job_uid(), Some("AAAA-AAAA"));
+    }
+
+    #[test]
+    fn filter_by_date_range() {
+        let files = vec![
+            png("AAAA-AAAA", "05-00-00", 1),
+            png("AAAA-AAAA", "06-00-00", 1),
+            png("AAAA-AAAA", "07-00-00", 1),
+        ];
+
+        let query = FileQuery {
+            from: Some(SIX_AM),
+            to: Some(SIX_AM + 3599),
+            ..Default::default()
+        };
+
+        assert_eq!(query.apply(files).total, 1);
+    }
+
+    #[test]
+    fn sort_by_size_ascending() {
+        let files = vec![
+            png("AAAA-AAAA", "06-00-00", 30),
+            png("AAAA-AAAA", "07-00-00", 10),
+            png("AAAA-AAAA", "08-00-00", 20),
+        ];
+
+        let query = FileQuery {
+            sort: FileSort::Size,
+            order: SortOrder::Asc,
+            ..Default::default()
+        };
+        let sizes: Vec<u64> = query.apply(files).files.iter().map(|f| f.size).collect();
+
+        assert_eq!(sizes, vec![10, 20, 30]);
+    }
+
+    #[test]
+    fn pagination() {
+        let files = (0..10)
+            .map(|i| png("AAAA-AAAA", &format!("06-00-{:02}", i), 1))
+            .collect();
+
+        let query = FileQuery {
+            offset: 8,
+            limit: Some(5),
+            ..Default::default()
+        };
+        let page = query.apply(files);
+
+        assert_eq!(page.total, 10);
+        assert_eq!(page.files.len(), 2);
+    }
+
+    #[test]
+    fn recording_path_rejects_traversal() {
+        let dir = Path::new("/var/recorder/recorded-files/");
+        assert!(recording_path(dir, "../jobs.json").is_none());
+        assert!(recording_path(dir, "..").is_none());
+        assert!(recording_path(dir, "gnss_pos/pos.txt").is_none());
+        assert!(recording_path(dir, "/etc/passwd").is_none());
+        assert!(recording_path(dir, "").is_none());
+    }
+
+    #[test]
+    fn recording_path_accepts_plain_names() {
+        let dir = Path::new("/var/recorder/recorded-files/");
+        let path = recording_path(dir, "KiwiRec_a.png").unwrap();
+        assert_eq!(path, dir.join("KiwiRec_a.png"));
+    }
+
+    #[test]
+    fn protected_while_recording() {
+        let active = vec!["ABCD-EFGH_2025-01-01_06-00-00_UTC_Fq1d42e7_Zm10".to_string()];
+        assert!(is_protected(
+            "KiwiRec_ABCD-EFGH_2025-01-01_06-00-00_UTC_Fq1d42e7_Zm10.png",
+            &active
+        ));
+        assert!(!is_protected(
+            "KiwiRec_ABCD-EFGH_2025-01-01_05-00-00_UTC_Fq1d42e7_Zm10.png",
+            &active
+        ));
+    }
+
+    #[test]
+    fn filter_requires_criteria() {
+        assert!(FileFilter::default().select(Vec::new(), 0).is_err());
+    }
+
+    #[test]
+    fn filter_rejects_bad_glob() {
+        let filter = FileFilter {
+            pattern: Some("[".into()),
+            ..Default::default()
+        };
+        assert!(filter.select(Vec::new(), 0).is_err());
+    }
+
+    #[test]
+    fn filter_combines_criteria() {
+        let files = vec![
+            png("AAAA-AAAA", "05-00-00", 1),
+            png("AAAA-AAAA", "07-00-00", 1),
+            iq("AAAA-AAAA", "05-00-00"),
+            png("BBBB-BBBB", "05-00-00", 1),
+        ];
+
+        let filter = FileFilter {
+            job_uid: Some("AAAA-AAAA".into()),
+            older_than: Some(3600),
+            pattern: Some("*.png".into()),
+        };
+        let selected = filter.select(files, SIX_AM + 1).unwrap();
+
+        assert_eq!(selected.len(), 1);
+        assert!(selected[0].name().contains("05-00-00"));
+    }
+
+    #[tokio::test]
+    async fn delete_refuses_directories() {
+        let dir = std::env::temp_dir().join(format!("kiwi-files-{}", crate::job::generate_uid()));
+        fs::create_dir_all(dir.join("gnss_pos")).await.unwrap();
+        fs::write(dir.join("a.png"), b"png").await.unwrap();
+
+        assert!(delete_recording(&dir.join("gnss_pos")).await.is_err());
+        delete_recording(&dir.join("a.png")).await.unwrap();
+        assert!(list_recordings(&dir).await.unwrap().is_empty());
+
+        let err = delete_recording(&dir.join("a.png")).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+
+        let _ = fs::remove_dir_all(&dir).await;
+    }
+
+    #[tokio::test]
+    async fn list_missing_dir_is_empty() {
+        let dir = std::env::temp_dir().join("kiwi-files-does-not-exist");
+        assert!(list_recordings(&dir).await.unwrap().is_empty());
+    }
+}
@@ -1,24 +1,194 @@
+use crate::config::{Config, RecorderConfig};
+use crate::kiwi::KiwiReceiver;
+use crate::retention::has_free_space;
+use crate::schedule::*;
 use crate::state::*;
 use chrono::Utc;
+use nix::errno::Errno;
+use nix::sys::signal::{Signal, kill};
+use nix::unistd::Pid;
 use rand::{Rng, thread_rng};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::collections::VecDeque;
 use std::fmt::{self, Display, Formatter};
 use std::io;
-use std::process::Stdio;
+use std::os::unix::process::ExitStatusExt;
+use std::process::{ExitStatus, Stdio};
 use std::sync::Arc;
 use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
 use tokio::process::Child;
-use tokio::sync::{Mutex, MutexGuard};
+use tokio::sync::{Mutex, MutexGuard, broadcast};
+use tokio::time::{Duration, sleep, timeout};
+
+const STDOUT: &str = "STDOUT";
+const STDERR: &str = "STDERR";
+
+/// Something that happened to a job, as opposed to output of the recorder process.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(tag = "type")]
+pub enum JobEvent {
+    Restored,
+    Started {
+        run: u32,
+        settings: RecorderSettings,
+    },
+    Retried {
+        attempt: u32,
+    },
+    Exited {
+        success: bool,
+        exit_code: Option<i32>,
+        signal: Option<i32>,
+    },
+    WaitFailed {
+        error: String,
+    },
+    StartFailed {
+        error: String,
+    },
+    RetryScheduled {
+        attempt: u32,
+        max_attempts: u32,
+        retry_at: u64, // Unix
+    },
+    RetriesExhausted {
+        attempts: u32,
+    },
+    StoppedManually,
+    Terminated {
+        graceful: bool,    // Exited after SIGINT, false if it had to be killed
+        grace_period: u64, // sec
+    },
+    Shutdown,
+    Queued,
+    Preempted {
+        by_job_id: u32,
+    },
+    RunNow {
+        reset_schedule: bool,
+    },
+    Paused,
+    Resumed {
+        next_run_start: Option<u64>, // Unix
+    },
+    SettingsChanged {
+        settings: RecorderSettings,
+    },
+}
+
+impl Display for JobEvent {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            JobEvent::Restored => write!(f, "<Restored>"),
+            JobEvent::Started { run, settings } => {
+                write!(f, "<Started> Run {}, {}", run, settings)
+            }
+            JobEvent::Retried { attempt } => write!(f, "<Retry> Attempt {}", attempt),
+            JobEvent::Exited {
+                exit_code: Some(code),
+                ..
+            } => write!(f, "<Exited> exit status: {}", code),
+            JobEvent::Exited {
+                signal: Some(signal),
+                ..
+            } => write!(f, "<Exited> signal: {}", signal),
+            JobEvent::Exited { .. } => write!(f, "<Exited>"),
+            JobEvent::WaitFailed { error } => write!(f, "<Wait Failed> {}", error),
+            JobEvent::StartFailed { error } => write!(f, "<Start Failed> {}", error),
+            JobEvent::RetryScheduled {
+                attempt,
+                max_attempts,
+                retry_at,
+            } => write!(
+                f,
+                "<Retry Scheduled> Attempt {} of {} at {}",
+                attempt, max_attempts, retry_at
+            ),
+            JobEvent::RetriesExhausted { attempts } => {
+                write!(f, "<Retries Exhausted> Gave up after {} attempts", attempts)
+            }
+            JobEvent::StoppedManually => write!(f, "<Stopped Manually>"),
+            JobEvent::Terminated { graceful: true, .. } => {
+                write!(f, "<Terminated> Exited after SIGINT")
+            }
+            JobEvent::Terminated { grace_period, .. } => write!(
+                f,
+                "<Terminated> Killed after not exiting within {} sec of SIGINT",
+                grace_period
+            ),
+            JobEvent::RunNow {
+                reset_schedule: true,
+            } => write!(f, "<Run Now> Schedule reset"),
+            JobEvent::RunNow { .. } => write!(f, "<Run Now>"),
+            JobEvent::Shutdown => write!(f, "<Shutdown> Stopping for backend shutdown"),
+            JobEvent::Queued => write!(f, "<Queued> Waiting for a free slot"),
+            JobEvent::Preempted { by_job_id } => {
+                write!(f, "<Preempted> Stopped for job {}, queued again", by_job_id)
+            }
+            JobEvent::Paused => write!(f, "<Paused>"),
+            JobEvent::Resumed {
+                next_run_start: Some(next),
+            } => write!(f, "<Resumed> Next run at {}", next),
+            JobEvent::Resumed { .. } => write!(f, "<Resumed>"),
+            JobEvent::SettingsChanged { settings } => {
+                write!(f, "<Settings Changed> {}", settings)
+            }
+        }
+    }
+}
 
-#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
 pub struct Log {
     timestamp: u64, // Unix
     data: String,
+    #[serde(default)]
+    event: Option<JobEvent>, // None for process output
 }
 
 impl Log {
+    pub fn new(timestamp: u64, data: String) -> Self {
+        Self {
+            timestamp,
+            data,
+            event: None,
+        }
+    }
+
+    /// A log for `event`, its data is the event's human readable form.
+    pub fn from_event(timestamp: u64, event: JobEvent) -> Self {
+        Self {
+            timestamp,
+            data: event.to_string(),
+            event: Some(event),
+        }
+    }
+
+    pub fn event(&self) -> Option<&JobEvent> {
+        self.event.as_ref()
+    }
+
+    pub fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    pub fn data(&self) -> &str {
+        &self.data
+    }
+
+    /// Where the log came from. Process output is told apart by the pipe tag in front of it.
+    pub fn source(&self) -> LogSource {
+        if self.event.is_some() {
+            LogSource::Event
+        } else if self.data.starts_with(&format!("<{}>", STDOUT)) {
+            LogSource::Stdout
+        } else if self.data.starts_with(&format!("<{}>", STDERR)) {
+            LogSource::Stderr
+        } else {
+            LogSource::Event
+        }
+    }
+
     fn get_truncated(&self) -> Self {
         const MAX_LOG_CHARS: usize = 200;
 
@@ -35,11 +205,12 @@ impl Log {
         Self {
             timestamp: self.timestamp,
             data: truncated_data,
+            event: self.event.clone(),
         }
     }
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Default)]
 pub struct Logs {
     logs: VecDeque<Log>,
 }
@@ -63,6 +234,10 @@ impl Logs {
         }
     }
 
+    pub fn iter(&self) -> impl Iterator<Item = &Log> {
+        self.logs.iter()
+    }
+
     pub fn push(&mut self, data: Log) {
         const MAX_LOG_COUNT: usize = 999;
 
@@ -74,11 +249,74 @@ impl Logs {
     }
 }
 
-#[derive(Deserialize, Serialize, Clone, Copy, Debug)]
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogSource {
+    Stdout,
+    Stderr,
+    Event, // `JobEvent`s pushed by the backend itself
+}
+
+#[derive(Deserialize, Clone, Debug, Default)]
+pub struct LogQuery {
+    since: Option<u64>, // Unix, inclusive
+    pipe: Option<LogSource>,
+    #[serde(default)]
+    offset: usize,
+    limit: Option<usize>,
+    #[serde(default)]
+    full: bool, // Return lines without truncating them
+}
+
+#[derive(Serialize, Debug)]
+pub struct LogPage {
+    total: usize, // Matching logs before pagination
+    offset: usize,
+    limit: usize,
+    logs: Vec<Log>,
+}
+
+impl LogQuery {
+    const DEFAULT_LIMIT: usize = 100;
+    const MAX_LIMIT: usize = 999;
+
+    fn matches(&self, log: &Log) -> bool {
+        self.since.is_none_or(|since| log.timestamp >= since)
+            && self.pipe.is_none_or(|pipe| log.source() == pipe)
+    }
+
+    /// Filters and paginates `logs`, oldest first.
+    pub fn apply(&self, logs: &Logs) -> LogPage {
+        let limit = self
+            .limit
+            .unwrap_or(Self::DEFAULT_LIMIT)
+            .min(Self::MAX_LIMIT);
+
+        let matching: Vec<&Log> = logs.iter().filter(|log| self.matches(log)).collect();
+
+        LogPage {
+            total: matching.len(),
+            offset: self.offset,
+            limit,
+            logs: matching
+                .into_iter()
+                .skip(self.offset)
+                .take(limit)
+                .map(|log| match self.full {
+                    true => log.clone(),
+                    false => log.get_truncated(),
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
 pub enum RecordingType {
     PNG,
     IQ,
+    Audio,
 }
 
 impl Display for RecordingType {
@@ -86,525 +324,2797 @@ impl Display for RecordingType {
         match self {
             RecordingType::PNG => write!(f, "Png"),
             RecordingType::IQ => write!(f, "Iq"),
+            RecordingType::Audio => write!(f, "Audio"),
         }
     }
 }
 
-#[derive(Debug)]
-pub enum RecorderSettingsError {
-    ZoomTooHigh,
-    FrequencyAboveMax,
-    FrequencyBelowMin,
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Modulation {
+    AM,
+    USB,
+    LSB,
+    CW,
+    NBFM,
 }
 
-impl Display for RecorderSettingsError {
-    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+impl Modulation {
+    /// Name used by kiwirecorder.py's `--modulation`
+    pub fn as_arg(&self) -> &'static str {
         match self {
-            RecorderSettingsError::ZoomTooHigh => write!(f, "Zoom too high"),
-            RecorderSettingsError::FrequencyAboveMax => write!(
-                f,
-                "The selected frequency range exceeds the maximum frequency"
-            ),
-            RecorderSettingsError::FrequencyBelowMin => write!(
-                f,
-                "The selected frequency range exceeds the minimum frequency"
-            ),
+            Modulation::AM => "am",
+            Modulation::USB => "usb",
+            Modulation::LSB => "lsb",
+            Modulation::CW => "cw",
+            Modulation::NBFM => "nbfm",
         }
     }
+
+    pub fn from_arg(arg: &str) -> Option<Self> {
+        [
+            Modulation::AM,
+            Modulation::USB,
+            Modulation::LSB,
+            Modulation::CW,
+            Modulation::NBFM,
+        ]
+        .into_iter()
+        .find(|modulation| modulation.as_arg() == arg)
+    }
 }
 
-#[derive(Deserialize, Serialize, Clone, Copy, Debug)]
-pub struct RecorderSettings {
-    rec_type: RecordingType,
-    frequency: u32, // Hz
-    #[serde(default)] // defaults zoom to 0 if not provided
-    zoom: u8,
-    duration: u16, // 0 == inf
+impl Display for Modulation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_arg().to_uppercase())
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AudioSettings {
+    modulation: Modulation,
     #[serde(default)]
-    interval: Option<u32>, // None == once
+    low_cut: Option<i32>, // Hz relative to the frequency, None == kiwirecorder default
+    #[serde(default)]
+    high_cut: Option<i32>, // Hz relative to the frequency, None == kiwirecorder default
+    #[serde(default)]
+    agc_gain: Option<u8>, // dB, None == AGC on
+    #[serde(default)]
+    squelch: Option<u8>, // dB above the noise floor, None == open
 }
 
-impl RecorderSettings {
+impl AudioSettings {
+    // The receiver's audio channel is 12 kHz wide
+    const MAX_CUT: i32 = 6_000;
+    const MAX_AGC_GAIN: u8 = 120;
+    const MAX_SQUELCH: u8 = 99;
+
     pub fn new(
-        rec_type: RecordingType,
-        frequency: u32,
-        zoom: u8,
-        duration: u16,
-        interval: Option<u32>,
+        modulation: Modulation,
+        passband: Option<(i32, i32)>,
+        agc_gain: Option<u8>,
+        squelch: Option<u8>,
     ) -> Self {
         Self {
-            rec_type,
-            frequency,
-            zoom,
-            duration,
-            interval,
+            modulation,
+            low_cut: passband.map(|(low, _)| low),
+            high_cut: passband.map(|(_, high)| high),
+            agc_gain,
+            squelch,
         }
     }
 
-    pub fn interval(&self) -> Option<u32> {
-        self.interval
+    fn passband(&self) -> Option<(i32, i32)> {
+        self.low_cut.zip(self.high_cut)
     }
 
-    pub fn validate(&self) -> Result<(), RecorderSettingsError> {
-        if self.zoom > 31 {
-            // Prevent bitshifting a u32 by 32 bits
-            return Err(RecorderSettingsError::ZoomTooHigh);
-        }
-
-        const MIN_FREQ: u32 = 0;
-        const MAX_FREQ: u32 = 30_000_000;
+    fn validate(&self, frequency: u32) -> Result<(), RecorderSettingsError> {
+        use RecorderSettingsError::InvalidAudioSettings as Invalid;
 
-        let zoom = self.zoom as u32;
-        let center_freq = self.frequency;
+        if self.low_cut.is_some() != self.high_cut.is_some() {
+            return Err(Invalid("low_cut and high_cut must be set together"));
+        }
 
-        let bandwidth = (MAX_FREQ - MIN_FREQ) / (1 << zoom); // "(1 << zoom)" bitshift is same as "(2^zoom)"
-        let selection_freq_max = center_freq.saturating_add(bandwidth / 2); // Saturating add/sub to avoid integer overflow
-        let selection_freq_min = (center_freq as i64).saturating_sub((bandwidth as i64) / 2);
+        if let Some((low, high)) = self.passband() {
+            if low >= high {
+                return Err(Invalid("low_cut must be below high_cut"));
+            }
+            if low < -Self::MAX_CUT || high > Self::MAX_CUT {
+                return Err(Invalid("The passband can't be wider than +-6000 Hz"));
+            }
+            if self.modulation == Modulation::USB && low < 0 {
+                return Err(Invalid("USB passband must be above the frequency"));
+            }
+            if self.modulation == Modulation::LSB && high > 0 {
+                return Err(Invalid("LSB passband must be below the frequency"));
+            }
+            validate_passband_range(frequency, low, high)?;
+        } else {
+            // kiwirecorder picks the passband, at least the frequency itself has to be in range
+            validate_passband_range(frequency, 0, 0)?;
+        }
 
-        if selection_freq_max > MAX_FREQ {
-            return Err(RecorderSettingsError::FrequencyAboveMax);
+        if self.agc_gain.is_some_and(|gain| gain > Self::MAX_AGC_GAIN) {
+            return Err(Invalid("agc_gain can't be above 120 dB"));
         }
-        if selection_freq_min < MIN_FREQ as i64 {
-            return Err(RecorderSettingsError::FrequencyBelowMin);
+        if self
+            .squelch
+            .is_some_and(|squelch| squelch > Self::MAX_SQUELCH)
+        {
+            return Err(Invalid("squelch can't be above 99 dB"));
         }
 
         Ok(())
     }
 
-    pub fn get_filename(&self, uid: &str) -> String {
-        let filename_common = format!(
-            "{}_{}_Fq{}",
-            uid,
-            Utc::now().format("%Y-%m-%d_%H-%M-%S_UTC"),
-            to_scientific(self.frequency)
-        );
+    fn as_args(&self) -> Vec<String> {
+        let mut args = vec![format!("--modulation={}", self.modulation.as_arg())];
 
-        match self.rec_type {
-            RecordingType::IQ => format!("{}_Bw1d2e4", filename_common),
-            RecordingType::PNG => format!("{}_Zm{}", filename_common, self.zoom),
+        if let Some((low, high)) = self.passband() {
+            args.push(format!("--lp-cutoff={}", low));
+            args.push(format!("--hp-cutoff={}", high));
+        }
+        if let Some(gain) = self.agc_gain {
+            args.push(format!("--agc-gain={}", gain));
+        }
+        if let Some(squelch) = self.squelch {
+            args.push(format!("--squelch-threshold={}", squelch));
         }
-    }
-
-    pub fn as_args(&self, uid: &str) -> Vec<String> {
-        let mut args: Vec<String> = vec![
-            "-s".into(),
-            "127.0.0.1".into(),
-            "-p".into(),
-            "8073".into(),
-            format!("--freq={:#.3}", (self.frequency as f64 / 1000.0)),
-            "-d".into(),
-            "/var/recorder/recorded-files/".into(),
-            "--filename=KiwiRec".into(),
-            format!("--station={}", self.get_filename(uid)),
-        ];
-
-        match self.rec_type {
-            RecordingType::PNG => args.extend([
-                "--wf".into(),
-                "--wf-png".into(),
-                "--speed=4".into(),
-                "--modulation=am".into(),
-                format!("--zoom={}", self.zoom),
-            ]),
-            RecordingType::IQ => args.extend(["--kiwi-wav".into(), "--modulation=iq".into()]),
-        };
-
-        if self.duration != 0 {
-            args.push(format!("--time-limit={}", self.duration));
-        };
 
         args
     }
 }
 
-impl Display for RecorderSettings {
+impl Display for AudioSettings {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        write!(
-            f,
-            "Type: {}, Frequency: {} Hz, {}{}, for {} sec",
-            self.rec_type,
-            self.frequency,
-            match self.rec_type {
-                RecordingType::PNG => format!("Zoom: {}, ", self.zoom),
-                RecordingType::IQ => "".to_string(),
-            },
-            match self.interval {
-                Some(..) => format!("Every {} sec", self.interval.unwrap()),
-                None => "Once".to_string(),
-            },
-            self.duration,
-        )
+        write!(f, "Modulation: {}", self.modulation)?;
+        if let Some((low, high)) = self.passband() {
+            write!(f, ", Passband: {} to {} Hz", low, high)?;
+        }
+        match self.agc_gain {
+            Some(gain) => write!(f, ", Gain: {} dB", gain)?,
+            None => write!(f, ", AGC")?,
+        }
+        if let Some(squelch) = self.squelch {
+            write!(f, ", Squelch: {} dB", squelch)?;
+        }
+        Ok(())
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
-pub enum JobStatus {
-    Idle,      // Waiting to start
-    Starting,  // Launching process
-    Running,   // Process active
-    Stopping, // Being stopped manually (if kiwirecorder.py gets a duration it will automaticly stop)
-    Completed, // One-shot job finished, never restart
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IqSettings {
+    #[serde(default)]
+    low_cut: Option<i32>, // Hz relative to the frequency, None == full channel
+    #[serde(default)]
+    high_cut: Option<i32>, // Hz relative to the frequency, None == full channel
+    #[serde(default = "IqSettings::default_gps_timestamps")]
+    gps_timestamps: bool, // Write kiwirecorder's GPS timestamp header into the wav
 }
 
-#[derive(Debug)]
-pub struct Job {
-    job_id: u32,
-    job_uid: String,
-    status: JobStatus,
-    process: Option<Child>,
-    started_at: Option<u64>,
-    next_run_start: Option<u64>,
-    logs: Logs,
-    settings: RecorderSettings,
+impl Default for IqSettings {
+    fn default() -> Self {
+        Self {
+            low_cut: None,
+            high_cut: None,
+            gps_timestamps: Self::default_gps_timestamps(),
+        }
+    }
 }
 
-impl Job {
-    pub fn new(job_id: u32, settings: RecorderSettings) -> Self {
+impl IqSettings {
+    // The receiver's IQ channel is 12 kHz wide
+    const MAX_CUT: i32 = 6_000;
+
+    pub fn new(passband: Option<(i32, i32)>, gps_timestamps: bool) -> Self {
         Self {
-            job_id,
-            job_uid: generate_uid(),
-            status: JobStatus::Idle,
-            process: None,
-            started_at: None,
-            next_run_start: None,
-            logs: Logs::default(),
-            settings,
+            low_cut: passband.map(|(low, _)| low),
+            high_cut: passband.map(|(_, high)| high),
+            gps_timestamps,
         }
     }
 
-    pub fn is_waiting_to_start(&self) -> bool {
-        let now = Utc::now().timestamp() as u64;
-
-        self.status == JobStatus::Idle
-            && self.next_run_start.unwrap_or(u64::MAX) <= now
-            && self.process.is_none()
+    fn default_gps_timestamps() -> bool {
+        true
     }
 
-    pub fn id(&self) -> u32 {
-        self.job_id
+    fn passband(&self) -> Option<(i32, i32)> {
+        self.low_cut.zip(self.high_cut)
     }
 
-    pub async fn start(shared_job: Arc<Mutex<Job>>) -> io::Result<()> {
-        let mut job = shared_job.lock().await;
-        job.mark_starting()?;
-        let uid = job.job_uid.clone();
-        let settings = job.settings;
-        drop(job);
+    /// The passband that is actually recorded, the full channel if none was given.
+    fn effective_passband(&self) -> (i32, i32) {
+        self.passband().unwrap_or((-Self::MAX_CUT, Self::MAX_CUT))
+    }
 
-        let mut child: Child = tokio::process::Command::new("python3")
-            .arg("kiwirecorder.py")
-            .args(settings.as_args(&uid))
-            .current_dir("/usr/local/src/kiwiclient/")
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .spawn()?;
+    fn validate(&self, frequency: u32) -> Result<(), RecorderSettingsError> {
+        use RecorderSettingsError::InvalidIqSettings as Invalid;
 
-        if let Some(stdout) = child.stdout.take() {
-            tokio::spawn(Self::read_output(
-                stdout,
-                shared_job.clone(),
-                "STDOUT",
-                true,
-            ));
+        if self.low_cut.is_some() != self.high_cut.is_some() {
+            return Err(Invalid("low_cut and high_cut must be set together"));
         }
 
-        if let Some(stderr) = child.stderr.take() {
-            tokio::spawn(Self::read_output(
-                stderr,
-                shared_job.clone(),
-                "STDERR",
-                false,
-            ));
+        let (low, high) = self.effective_passband();
+        if low >= high {
+            return Err(Invalid("low_cut must be below high_cut"));
+        }
+        if low < -Self::MAX_CUT || high > Self::MAX_CUT {
+            return Err(Invalid("The passband can't be wider than +-6000 Hz"));
         }
 
-        let mut job = shared_job.lock().await;
-        job.mark_running(child);
-
-        Ok(())
+        validate_passband_range(frequency, low, high)
     }
 
-    pub async fn stop(shared_job: Arc<Mutex<Job>>) -> io::Result<()> {
-        let mut job = shared_job.lock().await;
-        job.mark_stopping()?;
-        let child = job.process.take();
-        drop(job);
+    fn as_args(&self) -> Vec<String> {
+        let mut args = vec!["--modulation=iq".to_string()];
 
-        if let Some(mut child) = child {
-            child.kill().await?;
-            let _ = child.wait().await?;
+        if self.gps_timestamps {
+            args.push("--kiwi-wav".into());
+        }
+        if let Some((low, high)) = self.passband() {
+            args.push(format!("--lp-cutoff={}", low));
+            args.push(format!("--hp-cutoff={}", high));
         }
 
-        let mut job = shared_job.lock().await;
-        job.mark_stopped_manually();
+        args
+    }
+}
 
+impl Display for IqSettings {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let (low, high) = self.effective_passband();
+        write!(f, "Passband: {} to {} Hz", low, high)?;
+        if !self.gps_timestamps {
+            write!(f, ", No GPS timestamps")?;
+        }
         Ok(())
     }
+}
 
-    async fn read_output(
-        pipe: impl AsyncRead + Unpin,
-        job: Arc<Mutex<Job>>,
-        pipe_tag: &str,
-        responsible_for_exit: bool,
-    ) {
-        let reader = BufReader::new(pipe);
-        let mut lines = reader.lines();
-        while let Ok(Some(line)) = lines.next_line().await {
-            let mut state: MutexGuard<'_, Job> = job.lock().await;
-            state.push_log(format!("<{}> {}", pipe_tag, line));
+const MIN_FREQ: u32 = 0;
+const MAX_FREQ: u32 = 30_000_000;
+
+/// Checks that a passband relative to `frequency` stays within the receiver's range.
+fn validate_passband_range(
+    frequency: u32,
+    low: i32,
+    high: i32,
+) -> Result<(), RecorderSettingsError> {
+    if (frequency as i64) + (low as i64) < MIN_FREQ as i64 {
+        return Err(RecorderSettingsError::FrequencyBelowMin);
+    }
+    if (frequency as i64) + (high as i64) > MAX_FREQ as i64 {
+        return Err(RecorderSettingsError::FrequencyAboveMax);
+    }
+
+    Ok(())
+}
+
+#[derive(Debug)]
+pub enum RecorderSettingsError {
+    ZoomTooHigh,
+    FrequencyAboveMax,
+    FrequencyBelowMin,
+    InvalidCron(String),
+    ConflictingSchedules,
+    InvalidRetryPolicy(&'static str),
+    MissingAudioSettings,
+    InvalidAudioSettings(&'static str),
+    InvalidIqSettings(&'static str),
+}
+
+impl Display for RecorderSettingsError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            RecorderSettingsError::ZoomTooHigh => write!(f, "Zoom too high"),
+            RecorderSettingsError::FrequencyAboveMax => write!(
+                f,
+                "The selected frequency range exceeds the maximum frequency"
+            ),
+            RecorderSettingsError::FrequencyBelowMin => write!(
+                f,
+                "The selected frequency range exceeds the minimum frequency"
+            ),
+            RecorderSettingsError::InvalidCron(err) => {
+                write!(f, "Invalid cron expression: {}", err)
+            }
+            RecorderSettingsError::ConflictingSchedules => {
+                write!(f, "Only one of interval and cron can be set")
+            }
+            RecorderSettingsError::InvalidRetryPolicy(reason) => {
+                write!(f, "Invalid retry policy: {}", reason)
+            }
+            RecorderSettingsError::MissingAudioSettings => {
+                write!(f, "Audio recordings need audio settings")
+            }
+            RecorderSettingsError::InvalidAudioSettings(reason) => {
+                write!(f, "Invalid audio settings: {}", reason)
+            }
+            RecorderSettingsError::InvalidIqSettings(reason) => {
+                write!(f, "Invalid IQ settings: {}", reason)
+            }
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    initial_delay: u32, // sec
+    #[serde(default = "RetryPolicy::default_backoff_factor")]
+    backoff_factor: f64,
+}
+
+impl RetryPolicy {
+    const MAX_DELAY: u64 = 24 * 60 * 60;
+
+    pub fn new(max_attempts: u32, initial_delay: u32, backoff_factor: f64) -> Self {
+        Self {
+            max_attempts,
+            initial_delay,
+            backoff_factor,
+        }
+    }
+
+    fn default_backoff_factor() -> f64 {
+        2.0
+    }
+
+    pub fn max_attempts(&self) -> u32 {
+        self.max_attempts
+    }
+
+    fn validate(&self) -> Result<(), RecorderSettingsError> {
+        if !self.backoff_factor.is_finite() || self.backoff_factor < 1.0 {
+            return Err(RecorderSettingsError::InvalidRetryPolicy(
+                "backoff_factor must be at least 1",
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Seconds to wait before retry number `attempt` (starting at 1), capped at a day.
+    pub fn delay(&self, attempt: u32) -> u64 {
+        let exponent = attempt.saturating_sub(1).min(i32::MAX as u32) as i32;
+        let delay = self.initial_delay as f64 * self.backoff_factor.powi(exponent);
+
+        (delay as u64).min(Self::MAX_DELAY)
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct RecorderSettings {
+    rec_type: RecordingType,
+    frequency: u32, // Hz
+    #[serde(default)] // defaults zoom to 0 if not provided
+    zoom: u8,
+    duration: u16, // 0 == inf
+    #[serde(default)]
+    interval: Option<u32>, // None == once
+    #[serde(default)]
+    cron: Option<String>, // UTC, mutually exclusive with interval
+    #[serde(default)]
+    retry: Option<RetryPolicy>, // None == never retry a failed run
+    #[serde(default)]
+    audio: Option<AudioSettings>, // Only used by audio recordings
+    #[serde(default)]
+    iq: Option<IqSettings>, // Only used by IQ recordings, None == full channel with GPS timestamps
+    #[serde(default)]
+    priority: u8, // Higher priority jobs preempt lower priority ones when all slots are in use
+    #[serde(default)]
+    receiver: Option<String>, // Name of a configured receiver, None == the first one
+}
+
+impl RecorderSettings {
+    pub fn new(
+        rec_type: RecordingType,
+        frequency: u32,
+        zoom: u8,
+        duration: u16,
+        interval: Option<u32>,
+    ) -> Self {
+        Self {
+            rec_type,
+            frequency,
+            zoom,
+            duration,
+            interval,
+            cron: None,
+            retry: None,
+            audio: None,
+            iq: None,
+            priority: 0,
+            receiver: None,
+        }
+    }
+
+    pub fn with_receiver(mut self, receiver: impl Into<String>) -> Self {
+        self.receiver = Some(receiver.into());
+        self
+    }
+
+    pub fn receiver(&self) -> Option<&str> {
+        self.receiver.as_deref()
+    }
+
+    pub fn with_priority(mut self, priority: u8) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    pub fn priority(&self) -> u8 {
+        self.priority
+    }
+
+    pub fn with_iq(mut self, iq: IqSettings) -> Self {
+        self.iq = Some(iq);
+        self
+    }
+
+    pub fn with_audio(mut self, audio: AudioSettings) -> Self {
+        self.audio = Some(audio);
+        self
+    }
+
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = Some(retry);
+        self
+    }
+
+    pub fn with_cron(mut self, cron: impl Into<String>) -> Self {
+        self.cron = Some(cron.into());
+        self
+    }
+
+    pub fn duration(&self) -> u16 {
+        self.duration
+    }
+
+    pub fn interval(&self) -> Option<u32> {
+        self.interval
+    }
+
+    pub fn cron(&self) -> Option<&str> {
+        self.cron.as_deref()
+    }
+
+    /// True if the job should be started again after a run, false for one-shot jobs.
+    pub fn is_recurring(&self) -> bool {
+        self.interval.is_some() || self.cron.is_some()
+    }
+
+    /// Returns up to `count` upcoming start times for a job whose next run is not before `from`.
+    pub fn upcoming_runs(&self, from: u64, count: usize) -> Vec<u64> {
+        if let Some(expression) = &self.cron {
+            return match parse_cron(expression) {
+                // `from` itself is a valid fire time, so search from the second before it
+                Ok(schedule) => cron_runs_after(&schedule, from.saturating_sub(1), count),
+                Err(..) => Vec::new(),
+            };
+        }
+
+        match self.interval {
+            Some(0) | None => Vec::new(),
+            Some(interval) => (0..count as u64)
+                .map(|i| from + i * interval as u64)
+                .collect(),
+        }
+    }
+
+    /// Predicted runs as (start, end) of a job whose next run starts at `first`, up to
+    /// `until`. Runs without a duration last until `until`.
+    pub fn planned_runs(&self, first: u64, until: u64) -> Vec<(u64, u64)> {
+        let starts = if let Some(expression) = &self.cron {
+            match parse_cron(expression) {
+                Ok(schedule) => {
+                    cron_runs_between(&schedule, first.saturating_sub(1), until, MAX_PLANNED_RUNS)
+                }
+                Err(..) => Vec::new(),
+            }
+        } else {
+            match self.interval {
+                Some(interval) if interval > 0 => (first..until)
+                    .step_by(interval as usize)
+                    .take(MAX_PLANNED_RUNS)
+                    .collect(),
+                _ => (first..until).take(1).collect(),
+            }
+        };
+
+        starts
+            .into_iter()
+            .map(|start| match self.duration {
+                0 => (start, until),
+                duration => (start, (start + duration as u64).min(until)),
+            })
+            .collect()
+    }
+
+    /// Whether `other` runs at the same times, for as long.
+    pub fn same_schedule(&self, other: &RecorderSettings) -> bool {
+        self.cron == other.cron
+            && self.interval == other.interval
+            && self.duration == other.duration
+    }
+
+    /// When a newly created job first runs. Cron jobs wait for their first fire time,
+    /// everything else starts right away.
+    pub fn first_run_start(&self, now: u64) -> Option<u64> {
+        if self.cron.is_some() {
+            return self.upcoming_runs(now + 1, 1).first().copied();
+        }
+
+        Some(now)
+    }
+
+    /// The start time of the run after one that started at `started_at`.
+    fn next_run_start(&self, started_at: u64) -> Option<u64> {
+        if self.cron.is_some() {
+            return self.upcoming_runs(started_at + 1, 1).first().copied();
+        }
+
+        match self.interval {
+            Some(0) | None => None,
+            Some(interval) => Some(started_at + interval as u64),
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), RecorderSettingsError> {
+        if let Some(expression) = &self.cron {
+            if self.interval.is_some() {
+                return Err(RecorderSettingsError::ConflictingSchedules);
+            }
+            parse_cron(expression).map_err(RecorderSettingsError::InvalidCron)?;
+        }
+
+        if let Some(retry) = &self.retry {
+            retry.validate()?;
+        }
+
+        if self.rec_type == RecordingType::Audio {
+            return match &self.audio {
+                Some(audio) => audio.validate(self.frequency),
+                None => Err(RecorderSettingsError::MissingAudioSettings),
+            };
+        }
+
+        if self.rec_type == RecordingType::IQ {
+            return self.iq.unwrap_or_default().validate(self.frequency);
+        }
+
+        if self.zoom > 31 {
+            // Prevent bitshifting a u32 by 32 bits
+            return Err(RecorderSettingsError::ZoomTooHigh);
+        }
+
+        let zoom = self.zoom as u32;
+        let center_freq = self.frequency;
+
+        let bandwidth = (MAX_FREQ - MIN_FREQ) / (1 << zoom); // "(1 << zoom)" bitshift is same as "(2^zoom)"
+        let selection_freq_max = center_freq.saturating_add(bandwidth / 2); // Saturating add/sub to avoid integer overflow
+        let selection_freq_min = (center_freq as i64).saturating_sub((bandwidth as i64) / 2);
+
+        if selection_freq_max > MAX_FREQ {
+            return Err(RecorderSettingsError::FrequencyAboveMax);
+        }
+        if selection_freq_min < MIN_FREQ as i64 {
+            return Err(RecorderSettingsError::FrequencyBelowMin);
+        }
+
+        Ok(())
+    }
+
+    pub fn get_filename(&self, uid: &str) -> String {
+        let filename_common = format!(
+            "{}_{}_Fq{}",
+            uid,
+            Utc::now().format("%Y-%m-%d_%H-%M-%S_UTC"),
+            to_scientific(self.frequency)
+        );
+
+        match self.rec_type {
+            RecordingType::IQ => {
+                let (low, high) = self.iq.unwrap_or_default().effective_passband();
+                format!(
+                    "{}_Bw{}",
+                    filename_common,
+                    to_scientific(high.abs_diff(low))
+                )
+            }
+            RecordingType::PNG => format!("{}_Zm{}", filename_common, self.zoom),
+            RecordingType::Audio => match &self.audio {
+                Some(audio) => match audio.passband() {
+                    Some((low, high)) => format!(
+                        "{}_Md{}_Bw{}",
+                        filename_common,
+                        audio.modulation.as_arg(),
+                        to_scientific(high.abs_diff(low))
+                    ),
+                    None => format!("{}_Md{}", filename_common, audio.modulation.as_arg()),
+                },
+                None => filename_common,
+            },
+        }
+    }
+
+    pub fn as_args(
+        &self,
+        uid: &str,
+        receiver: &KiwiReceiver,
+        config: &RecorderConfig,
+    ) -> Vec<String> {
+        self.as_args_with_station(&self.get_filename(uid), receiver, config)
+    }
+
+    /// Like `as_args`, for a station name already made by `get_filename`.
+    pub fn as_args_with_station(
+        &self,
+        station: &str,
+        receiver: &KiwiReceiver,
+        config: &RecorderConfig,
+    ) -> Vec<String> {
+        let mut args: Vec<String> = vec![
+            "-s".into(),
+            receiver.host.clone(),
+            "-p".into(),
+            receiver.port.to_string(),
+            format!("--freq={:#.3}", (self.frequency as f64 / 1000.0)),
+        ];
+        args.extend(receiver.credential_args());
+        args.extend([
+            "-d".into(),
+            config.output_dir.to_string_lossy().into_owned(),
+            "--filename=KiwiRec".into(),
+            format!("--station={}", station),
+        ]);
+
+        match self.rec_type {
+            RecordingType::PNG => args.extend([
+                "--wf".into(),
+                "--wf-png".into(),
+                "--speed=4".into(),
+                "--modulation=am".into(),
+                format!("--zoom={}", self.zoom),
+            ]),
+            RecordingType::IQ => args.extend(self.iq.unwrap_or_default().as_args()),
+            RecordingType::Audio => {
+                if let Some(audio) = &self.audio {
+                    args.extend(audio.as_args());
+                }
+            }
+        };
+
+        if self.duration != 0 {
+            args.push(format!("--time-limit={}", self.duration));
+        };
+
+        args
+    }
+}
+
+impl Display for RecorderSettings {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Type: {}, Frequency: {} Hz, {}{}, for {} sec{}{}{}",
+            self.rec_type,
+            self.frequency,
+            match (self.rec_type, &self.audio) {
+                (RecordingType::PNG, _) => format!("Zoom: {}, ", self.zoom),
+                (RecordingType::IQ, _) => format!("{}, ", self.iq.unwrap_or_default()),
+                (RecordingType::Audio, Some(audio)) => format!("{}, ", audio),
+                _ => "".to_string(),
+            },
+            match (&self.cron, self.interval) {
+                (Some(cron), _) => format!("Cron '{}'", cron),
+                (None, Some(interval)) => format!("Every {} sec", interval),
+                (None, None) => "Once".to_string(),
+            },
+            self.duration,
+            match self.retry {
+                Some(retry) => format!(", Retry up to {} times", retry.max_attempts),
+                None => "".to_string(),
+            },
+            match self.priority {
+                0 => "".to_string(),
+                priority => format!(", Priority {}", priority),
+            },
+            match &self.receiver {
+                Some(receiver) => format!(", Receiver {}", receiver),
+                None => "".to_string(),
+            },
+        )
+    }
+}
+
+/// When new settings take effect on a job that is recording.
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ApplyMode {
+    Now, // Restart the current run with the new settings
+    #[default]
+    NextRun, // Let the current run finish first
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct SettingsUpdate {
+    pub settings: RecorderSettings,
+    #[serde(default)]
+    pub apply: ApplyMode,
+    #[serde(default)]
+    pub allow_conflicts: bool, // Accept the settings even if the new schedule collides with other jobs
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, Default)]
+pub struct StartOptions {
+    #[serde(default)]
+    pub queue: bool, // Wait for a free slot instead of failing when all are in use
+    #[serde(default)]
+    pub allow_conflicts: bool, // Accept the job even if its schedule collides with other jobs
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, Default)]
+pub struct RunNowOptions {
+    #[serde(default)]
+    pub reset_schedule: bool, // Count the next interval from this run instead of keeping the cadence
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum JobStatus {
+    Idle,      // Waiting to start
+    Starting,  // Launching process
+    Running,   // Process active
+    Stopping, // Being stopped manually (if kiwirecorder.py gets a duration it will automaticly stop)
+    Completed, // One-shot job finished, never restart
+    Paused,   // Recurring job suspended, the scheduler ignores it until it is resumed
+    Queued,   // Waiting for a free recorder slot, started before any scheduled job
+    Failed {
+        // Last run exited unsuccessfully, recurring jobs still start again
+        exit_code: Option<i32>,
+        signal: Option<i32>,
+    },
+}
+
+/// A failed run and what the retry policy decided to do about it.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct RetryAttempt {
+    run: u32,
+    failed_at: u64,        // Unix
+    attempt: u32,          // Retry number this failure led to, 0 if none
+    retry_at: Option<u64>, // Unix, None == retries exhausted
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Default)]
+pub struct RetryState {
+    attempt: u32,          // Retries since the last successful run
+    retry_at: Option<u64>, // Unix, when the pending retry starts
+    history: VecDeque<RetryAttempt>,
+}
+
+impl RetryState {
+    fn push_history(&mut self, attempt: RetryAttempt) {
+        const MAX_HISTORY_COUNT: usize = 20;
+
+        self.history.push_back(attempt);
+        if self.history.len() > MAX_HISTORY_COUNT {
+            self.history.pop_front();
+        }
+    }
+}
+
+/// How the last run of a job ended.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ExitReport {
+    run: u32,
+    exited_at: u64, // Unix
+    success: bool,
+    exit_code: Option<i32>,
+    signal: Option<i32>,
+    stderr_tail: Vec<String>, // Last lines written to stderr, oldest first
+}
+
+#[derive(Debug)]
+pub struct Job {
+    job_id: u32,
+    job_uid: String,
+    status: JobStatus,
+    process: Option<Child>,
+    started_at: Option<u64>,
+    next_run_start: Option<u64>,
+    run_count: u32,
+    last_exit: Option<ExitReport>,
+    retry: RetryState,
+    stderr_tail: VecDeque<String>,
+    recording: Option<String>, // Station name of the file(s) being written by the active run
+    logs: Logs,
+    log_sender: broadcast::Sender<Log>, // Live feed of every pushed log, for streaming
+    settings: RecorderSettings,
+    pending_settings: Option<RecorderSettings>, // Applied once the active run ends
+    scheduled_run: Option<u64>, // Regular next run of a preempted job, kept while it is queued
+}
+
+impl Job {
+    pub fn new(job_id: u32, settings: RecorderSettings) -> Self {
+        let next_run_start = match settings.cron {
+            Some(..) => settings.first_run_start(Utc::now().timestamp() as u64),
+            None => None,
+        };
+
+        Self {
+            job_id,
+            job_uid: generate_uid(),
+            status: JobStatus::Idle,
+            process: None,
+            started_at: None,
+            next_run_start,
+            run_count: 0,
+            last_exit: None,
+            retry: RetryState::default(),
+            stderr_tail: VecDeque::new(),
+            recording: None,
+            logs: Logs::default(),
+            log_sender: new_log_sender(),
+            settings,
+            pending_settings: None,
+            scheduled_run: None,
+        }
+    }
+
+    pub fn restore(record: JobRecord) -> Self {
+        let now = Utc::now().timestamp() as u64;
+
+        // A process can't survive a backend restart, so anything that was active is treated as exited
+        let status = match record.status {
+            JobStatus::Starting | JobStatus::Running | JobStatus::Stopping => {
+                if record.settings.is_recurring() {
+                    JobStatus::Idle
+                } else {
+                    JobStatus::Completed
+                }
+            }
+            status => status,
+        };
+
+        let schedulable = is_schedulable(status, &record.settings);
+        let next_run_start = match (record.settings.interval, record.next_run_start) {
+            (_, Some(next)) if schedulable && next < now && record.settings.cron.is_some() => {
+                record.settings.upcoming_runs(now, 1).first().copied()
+            }
+            (Some(interval), Some(next)) if schedulable && interval > 0 => {
+                Some(next_run_after(next, interval as u64, now))
+            }
+            _ => record.next_run_start,
+        };
+
+        let mut job = Self {
+            job_id: record.job_id,
+            job_uid: record.job_uid,
+            status,
+            process: None,
+            started_at: None,
+            next_run_start,
+            run_count: record.run_count,
+            last_exit: record.last_exit,
+            retry: record.retry,
+            stderr_tail: VecDeque::new(),
+            recording: None,
+            logs: Logs::default(),
+            log_sender: new_log_sender(),
+            settings: record.settings,
+            pending_settings: None,
+            scheduled_run: None,
+        };
+        job.push_event(JobEvent::Restored);
+
+        // The run these were waiting for ended with the restart
+        if let Some(settings) = record.pending_settings {
+            job.apply_settings(settings);
+        }
+        job
+    }
+
+    pub fn is_waiting_to_start(&self) -> bool {
+        let now = Utc::now().timestamp() as u64;
+
+        self.can_start() && self.due_at().unwrap_or(u64::MAX) <= now && self.process.is_none()
+    }
+
+    /// Whether the scheduler may start this job, either on its schedule or as a retry.
+    fn can_start(&self) -> bool {
+        is_schedulable(self.status, &self.settings)
+            || self.status == JobStatus::Queued
+            || matches!(self.status, JobStatus::Failed { .. }) && self.retry.retry_at.is_some()
+    }
+
+    /// The earliest of the next scheduled run and a pending retry.
+    fn due_at(&self) -> Option<u64> {
+        let next_run_start =
+            match is_schedulable(self.status, &self.settings) || self.status == JobStatus::Queued {
+                true => self.next_run_start,
+                false => None,
+            };
+
+        match (next_run_start, self.retry.retry_at) {
+            (Some(next), Some(retry)) => Some(next.min(retry)),
+            (next, retry) => next.or(retry),
+        }
+    }
+
+    pub fn id(&self) -> u32 {
+        self.job_id
+    }
+
+    pub fn status(&self) -> JobStatus {
+        self.status
+    }
+
+    pub fn next_run_start(&self) -> Option<u64> {
+        self.next_run_start
+    }
+
+    pub fn started_at(&self) -> Option<u64> {
+        self.started_at
+    }
+
+    /// Whether the job holds one of the receiver's channels.
+    pub fn occupies_slot(&self) -> bool {
+        matches!(
+            self.status,
+            JobStatus::Starting | JobStatus::Running | JobStatus::Stopping
+        )
+    }
+
+    /// When the slot held by this job frees up, None if the run has no duration.
+    pub fn slot_free_at(&self) -> Option<u64> {
+        match self.settings.duration {
+            0 => None,
+            duration => Some(self.started_at.unwrap_or_default() + duration as u64),
+        }
+    }
+
+    /// Predicted runs as (start, end) between `now` and `until`, the current run first.
+    pub fn planned_runs(&self, now: u64, until: u64) -> Vec<(u64, u64)> {
+        let mut runs = Vec::new();
+        if self.occupies_slot() {
+            let end = self.slot_free_at().unwrap_or(until).min(until);
+            runs.push((now, end));
+        }
+
+        if let Some(next) = self.next_run_start {
+            // Queued jobs are overdue and start as soon as they can
+            let next = match self.status {
+                JobStatus::Queued => next.max(now),
+                _ => next,
+            };
+            runs.extend(self.settings.planned_runs(next, until));
+        }
+
+        runs.retain(|(_, end)| *end > now);
+        runs
+    }
+
+    /// Puts a new job in line for a free slot, the scheduler starts it once one frees up.
+    pub fn queue(&mut self) {
+        debug_assert!(self.status == JobStatus::Idle && self.process.is_none());
+
+        self.status = JobStatus::Queued;
+        self.next_run_start = Some(Utc::now().timestamp() as u64);
+        self.push_event(JobEvent::Queued);
+    }
+
+    pub fn uid(&self) -> &str {
+        &self.job_uid
+    }
+
+    pub fn settings(&self) -> &RecorderSettings {
+        &self.settings
+    }
+
+    pub fn logs(&self) -> &Logs {
+        &self.logs
+    }
+
+    /// The buffered logs, oldest first, and a receiver for every log pushed after them.
+    pub fn subscribe_logs(&self) -> (Vec<Log>, broadcast::Receiver<Log>) {
+        (
+            self.logs.iter().cloned().collect(),
+            self.log_sender.subscribe(),
+        )
+    }
+
+    /// Station name of the recording currently being written, if any.
+    pub fn active_recording(&self) -> Option<&str> {
+        self.recording.as_deref()
+    }
+
+    pub async fn start(shared_job: Arc<Mutex<Job>>, config: &Config) -> io::Result<()> {
+        let mut job = shared_job.lock().await;
+        job.mark_starting()?;
+
+        Self::launch(&shared_job, job, config).await
+    }
+
+    /// Spawns the recorder for a job that was marked as starting under `job`.
+    async fn launch(
+        shared_job: &Arc<Mutex<Job>>,
+        mut job: MutexGuard<'_, Job>,
+        config: &Config,
+    ) -> io::Result<()> {
+        let recorder = &config.recorder;
+        if !has_free_space(&recorder.output_dir, config.retention.min_free_bytes) {
+            let err = io::Error::new(io::ErrorKind::StorageFull, "Not enough free disk space");
+            job.mark_start_failed(&err);
+            return Err(err);
+        }
+
+        let Some(receiver) = config.receiver(job.settings.receiver()) else {
+            let err = io::Error::new(io::ErrorKind::NotFound, "Receiver is not configured");
+            job.mark_start_failed(&err);
+            return Err(err);
+        };
+
+        let station = job.settings.get_filename(&job.job_uid);
+        let settings = job.settings.clone();
+        drop(job);
+
+        let spawned = tokio::process::Command::new(&recorder.python)
+            .arg("kiwirecorder.py")
+            .args(settings.as_args_with_station(&station, &receiver, recorder))
+            .current_dir(&recorder.kiwiclient_dir)
+            // Never leave a recorder behind if the backend gives up on stopping it
+            .kill_on_drop(true)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn();
+
+        let mut child: Child = match spawned {
+            Ok(child) => child,
+            Err(err) => {
+                shared_job.lock().await.mark_start_failed(&err);
+                return Err(err);
+            }
+        };
+
+        if let Some(stdout) = child.stdout.take() {
+            tokio::spawn(Self::read_output(stdout, shared_job.clone(), STDOUT, true));
+        }
+
+        if let Some(stderr) = child.stderr.take() {
+            tokio::spawn(Self::read_output(stderr, shared_job.clone(), STDERR, false));
+        }
+
+        let mut job = shared_job.lock().await;
+        job.mark_running(child, station);
+
+        Ok(())
+    }
+
+    /// Stops the active run and starts a new one with `settings`. The job stays locked from
+    /// the end of the old run until the new one is starting, so the scheduler can't step in.
+    pub async fn restart_with_settings(
+        shared_job: Arc<Mutex<Job>>,
+        settings: RecorderSettings,
+        config: &Config,
+    ) -> io::Result<()> {
+        let mut job = Self::stop_locked(&shared_job, config).await?;
+        // A stopped one-shot job is completed, but it should still run with its new settings
+        job.status = JobStatus::Idle;
+        job.apply_settings(settings);
+        job.mark_starting()?;
+
+        Self::launch(&shared_job, job, config).await
+    }
+
+    /// Starts a job outside of its schedule. Unless `options.reset_schedule` is set,
+    /// the next scheduled run stays where it was.
+    pub async fn run_now(
+        shared_job: Arc<Mutex<Job>>,
+        options: RunNowOptions,
+        config: &Config,
+    ) -> io::Result<()> {
+        let scheduled = {
+            let mut job = shared_job.lock().await;
+            match job.status {
+                JobStatus::Idle | JobStatus::Completed | JobStatus::Failed { .. } => {}
+                _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, "Job not idle")),
+            }
+
+            // Lets finished one-shot jobs and failed jobs without retries start once more
+            job.status = JobStatus::Idle;
+            job.push_event(JobEvent::RunNow {
+                reset_schedule: options.reset_schedule,
+            });
+            job.next_run_start
+        };
+
+        Self::start(shared_job.clone(), config).await?;
+
+        let now = Utc::now().timestamp() as u64;
+        let mut job = shared_job.lock().await;
+        if !options.reset_schedule
+            && let Some(scheduled) = scheduled.filter(|&scheduled| scheduled > now)
+        {
+            job.next_run_start = Some(scheduled);
+        }
+
+        Ok(())
+    }
+
+    /// Stops the active run, giving the recorder `stop_grace_period` to finish its files.
+    pub async fn stop(shared_job: Arc<Mutex<Job>>, config: &Config) -> io::Result<()> {
+        drop(Self::stop_locked(&shared_job, config).await?);
+        Ok(())
+    }
+
+    /// Like `stop`, but hands back the job still locked, so the caller decides what happens
+    /// next before anyone else sees it stopped.
+    async fn stop_locked<'a>(
+        shared_job: &'a Arc<Mutex<Job>>,
+        config: &Config,
+    ) -> io::Result<MutexGuard<'a, Job>> {
+        let mut job = shared_job.lock().await;
+        job.mark_stopping()?;
+        let child = job.process.take();
+        drop(job);
+
+        if let Some(mut child) = child {
+            let grace_period = config.recorder.stop_grace_period;
+            let graceful = terminate(&mut child, Duration::from_secs(grace_period)).await?;
+            shared_job.lock().await.push_event(JobEvent::Terminated {
+                graceful,
+                grace_period,
+            });
+        }
+
+        let mut job = shared_job.lock().await;
+        job.mark_stopped_manually();
+
+        Ok(job)
+    }
+
+    async fn read_output(
+        pipe: impl AsyncRead + Unpin,
+        job: Arc<Mutex<Job>>,
+        pipe_tag: &str,
+        responsible_for_exit: bool,
+    ) {
+        let reader = BufReader::new(pipe);
+        let mut lines = reader.lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            let mut state: MutexGuard<'_, Job> = job.lock().await;
+            if pipe_tag == STDERR {
+                state.push_stderr_tail(line.clone());
+            }
+            state.push_log(format!("<{}> {}", pipe_tag, line));
+        }
+        if responsible_for_exit {
+            Self::wait_for_exit(job).await;
+        }
+    }
+
+    /// Polls the child until it exits. The child stays inside the job while polling,
+    /// so `Job::stop` can still take and terminate it, in which case the stop path reports the exit.
+    async fn wait_for_exit(job: Arc<Mutex<Job>>) {
+        const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+        loop {
+            {
+                let mut state: MutexGuard<'_, Job> = job.lock().await;
+                if state.status != JobStatus::Running {
+                    return;
+                }
+
+                let Some(process) = state.process.as_mut() else {
+                    return;
+                };
+
+                match process.try_wait() {
+                    Ok(Some(exit_status)) => {
+                        state.mark_exited(exit_status);
+                        return;
+                    }
+                    Ok(None) => {}
+                    Err(err) => {
+                        state.push_event(JobEvent::WaitFailed {
+                            error: err.to_string(),
+                        });
+                        return;
+                    }
+                }
+            }
+
+            sleep(POLL_INTERVAL).await;
+        }
+    }
+
+    fn push_stderr_tail(&mut self, line: String) {
+        const STDERR_TAIL_COUNT: usize = 10;
+
+        self.stderr_tail.push_back(line);
+        if self.stderr_tail.len() > STDERR_TAIL_COUNT {
+            self.stderr_tail.pop_front();
+        }
+    }
+
+    fn push_log(&mut self, data: String) {
+        self.push(Log::new(Utc::now().timestamp() as u64, data));
+    }
+
+    fn push_event(&mut self, event: JobEvent) {
+        self.push(Log::from_event(Utc::now().timestamp() as u64, event));
+    }
+
+    fn push(&mut self, log: Log) {
+        // Sending only fails if nobody is streaming the logs
+        let _ = self.log_sender.send(log.clone());
+        self.logs.push(log);
+    }
+
+    /// Replaces the job's settings. A job that is recording keeps its current settings until
+    /// the run ends, unless `apply` is `Now`, in which case true is returned and the caller
+    /// has to restart it with `restart_with_settings`.
+    pub fn update_settings(
+        &mut self,
+        settings: RecorderSettings,
+        apply: ApplyMode,
+    ) -> io::Result<bool> {
+        match (self.status, apply) {
+            (JobStatus::Starting | JobStatus::Stopping, ApplyMode::Now) => Err(io::Error::new(
+                io::ErrorKind::ResourceBusy,
+                "Job is starting or stopping",
+            )),
+            (JobStatus::Running, ApplyMode::Now) => Ok(true),
+            (JobStatus::Starting | JobStatus::Running | JobStatus::Stopping, _) => {
+                self.pending_settings = Some(settings);
+                Ok(false)
+            }
+            _ => {
+                self.apply_settings(settings);
+                Ok(false)
+            }
+        }
+    }
+
+    /// Switches to `settings` while no run is active and moves the next run to match them.
+    fn apply_settings(&mut self, settings: RecorderSettings) {
+        let now = Utc::now().timestamp() as u64;
+
+        // A paused job gets its next run when it is resumed
+        if self.status != JobStatus::Paused {
+            self.next_run_start = self.rescheduled_run_start(&settings, now);
+        }
+        if self.status == JobStatus::Completed && settings.is_recurring() {
+            self.status = JobStatus::Idle;
+        }
+
+        self.settings = settings;
+        self.pending_settings = None;
+        self.scheduled_run = None;
+        self.push_event(JobEvent::SettingsChanged {
+            settings: self.settings.clone(),
+        });
+    }
+
+    /// When the job would run next if it switched to `settings` now, None if it wouldn't.
+    pub fn next_run_with(&self, settings: &RecorderSettings, now: u64) -> Option<u64> {
+        match self.status {
+            JobStatus::Paused => None,
+            _ => self.rescheduled_run_start(settings, now),
+        }
+    }
+
+    /// The next run of a job that is put back on a schedule, skipping runs that were missed.
+    /// Interval jobs keep counting from the last run's start.
+    fn rescheduled_run_start(&self, settings: &RecorderSettings, now: u64) -> Option<u64> {
+        match self.started_at {
+            Some(started_at) if settings.cron.is_none() => settings
+                .next_run_start(started_at)
+                .map(|next| next.max(now)),
+            _ if settings.is_recurring() || self.run_count == 0 => settings.first_run_start(now),
+            _ => None,
+        }
+    }
+
+    /// Stops the active run because the backend is exiting. The job ends up as if it was
+    /// stopped through the API: recurring jobs run again on their schedule after a restart,
+    /// one-shot jobs are completed.
+    pub async fn shutdown(shared_job: Arc<Mutex<Job>>, config: &Config) -> io::Result<()> {
+        shared_job.lock().await.push_event(JobEvent::Shutdown);
+        Self::stop(shared_job, config).await
+    }
+
+    /// Stops the active run to free its slot for the job `by_job_id`, and queues this job
+    /// so it records again once a slot is free. Recurring jobs keep their next regular run.
+    pub async fn preempt(
+        shared_job: Arc<Mutex<Job>>,
+        by_job_id: u32,
+        config: &Config,
+    ) -> io::Result<()> {
+        let mut job = Self::stop_locked(&shared_job, config).await?;
+        let scheduled = job.next_run_start.filter(|_| job.settings.is_recurring());
+        // A stopped one-shot job is completed, but its run was cut short
+        job.status = JobStatus::Idle;
+        job.queue();
+        job.scheduled_run = scheduled;
+        job.push_event(JobEvent::Preempted { by_job_id });
+
+        Ok(())
+    }
+
+    /// Stops the active run, if any, and suspends the job's schedule until `resume` is called.
+    pub async fn pause(shared_job: Arc<Mutex<Job>>, config: &Config) -> io::Result<()> {
+        let running = {
+            let job = shared_job.lock().await;
+            if !job.settings.is_recurring() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Only recurring jobs can be paused",
+                ));
+            }
+
+            match job.status {
+                JobStatus::Starting | JobStatus::Stopping => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::ResourceBusy,
+                        "Job is starting or stopping",
+                    ));
+                }
+                status => status == JobStatus::Running,
+            }
+        };
+
+        if running {
+            Self::stop(shared_job.clone(), config).await?;
+        }
+
+        shared_job.lock().await.mark_paused();
+        Ok(())
+    }
+
+    fn mark_paused(&mut self) {
+        if self.status == JobStatus::Paused {
+            return;
+        }
+
+        self.status = JobStatus::Paused;
+        self.next_run_start = None;
+        self.scheduled_run = None;
+        self.retry.retry_at = None;
+        self.push_event(JobEvent::Paused);
+    }
+
+    /// Puts a paused job back on its schedule. Runs missed while paused are skipped.
+    pub fn resume(&mut self) -> io::Result<()> {
+        if self.status != JobStatus::Paused {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Job is not paused",
+            ));
+        }
+
+        let now = Utc::now().timestamp() as u64;
+        self.status = JobStatus::Idle;
+        self.next_run_start = self.rescheduled_run_start(&self.settings, now);
+        self.push_event(JobEvent::Resumed {
+            next_run_start: self.next_run_start,
+        });
+
+        Ok(())
+    }
+
+    fn mark_starting(&mut self) -> io::Result<()> {
+        debug_assert!(self.process.is_none());
+
+        if !self.can_start() {
+            return Err(io::Error::other("Job not idle"));
+        }
+
+        self.status = JobStatus::Starting;
+        Ok(())
+    }
+
+    fn mark_running(&mut self, process: Child, station: String) {
+        debug_assert!(self.status == JobStatus::Starting);
+        let now = Utc::now().timestamp() as u64;
+
+        self.status = JobStatus::Running;
+        self.process = Some(process);
+        self.recording = Some(station);
+        self.started_at = Some(now);
+        self.run_count += 1;
+        self.stderr_tail.clear();
+
+        // A retry fills in for a failed run, so it doesn't move the regular schedule
+        let retrying = self.retry.retry_at.take().is_some();
+        if !retrying || self.next_run_start.is_none_or(|next| next <= now) {
+            self.next_run_start = self.settings.next_run_start(now);
+        }
+        // A preempted run that is made up for doesn't move the regular schedule either
+        if let Some(scheduled) = self
+            .scheduled_run
+            .take()
+            .filter(|&scheduled| scheduled > now)
+        {
+            self.next_run_start = Some(scheduled);
+        }
+
+        self.push_event(JobEvent::Started {
+            run: self.run_count,
+            settings: self.settings.clone(),
+        });
+        if retrying {
+            self.push_event(JobEvent::Retried {
+                attempt: self.retry.attempt,
+            });
+        }
+    }
+
+    fn mark_stopping(&mut self) -> io::Result<()> {
+        if self.status != JobStatus::Running {
+            return Err(io::Error::other("Job is not running"));
+        }
+
+        self.status = JobStatus::Stopping;
+        Ok(())
+    }
+
+    fn mark_exited(&mut self, exit_status: ExitStatus) {
+        debug_assert!(
+            self.status == JobStatus::Running,
+            "mark_exited called, but job status was {:?}",
+            self.status
+        );
+
+        let report = self.exit_report(
+            exit_status.success(),
+            exit_status.code(),
+            exit_status.signal(),
+        );
+
+        self.status = if !report.success {
+            JobStatus::Failed {
+                exit_code: report.exit_code,
+                signal: report.signal,
+            }
+        } else if self.settings.is_recurring() {
+            JobStatus::Idle
+        } else {
+            // One-shot jobs move to Completed so they dont start again
+            JobStatus::Completed
+        };
+        self.process = None;
+        self.recording = None;
+        self.skip_missed_cron_runs();
+        self.push_event(JobEvent::Exited {
+            success: report.success,
+            exit_code: report.exit_code,
+            signal: report.signal,
+        });
+
+        if report.success {
+            self.retry.attempt = 0;
+        } else {
+            self.schedule_retry();
+        }
+        self.last_exit = Some(report);
+
+        if let Some(settings) = self.pending_settings.take() {
+            self.apply_settings(settings);
+        }
+    }
+
+    /// Schedules a retry of a failed run if the retry policy has attempts left.
+    fn schedule_retry(&mut self) {
+        let Some(policy) = self.settings.retry else {
+            return;
+        };
+        let now = Utc::now().timestamp() as u64;
+
+        let retry_at = if self.retry.attempt < policy.max_attempts {
+            self.retry.attempt += 1;
+            Some(now + policy.delay(self.retry.attempt))
+        } else {
+            None
+        };
+
+        self.retry.retry_at = retry_at;
+        self.retry.push_history(RetryAttempt {
+            run: self.run_count,
+            failed_at: now,
+            attempt: if retry_at.is_some() {
+                self.retry.attempt
+            } else {
+                0
+            },
+            retry_at,
+        });
+
+        match retry_at {
+            Some(retry_at) => self.push_event(JobEvent::RetryScheduled {
+                attempt: self.retry.attempt,
+                max_attempts: policy.max_attempts,
+                retry_at,
+            }),
+            None => self.push_event(JobEvent::RetriesExhausted {
+                attempts: policy.max_attempts,
+            }),
+        }
+    }
+
+    fn mark_start_failed(&mut self, err: &io::Error) {
+        debug_assert!(self.status == JobStatus::Starting);
+        let now = Utc::now().timestamp() as u64;
+
+        // Skip the run that couldn't start, instead of trying again every scheduler tick
+        if self.next_run_start.is_some_and(|next| next <= now) {
+            self.next_run_start = self.settings.next_run_start(now);
+        }
+
+        self.run_count += 1;
+        self.stderr_tail = VecDeque::from([err.to_string()]);
+        self.last_exit = Some(self.exit_report(false, None, None));
+        self.status = JobStatus::Failed {
+            exit_code: None,
+            signal: None,
+        };
+        self.push_event(JobEvent::StartFailed {
+            error: err.to_string(),
+        });
+        self.schedule_retry();
+    }
+
+    fn exit_report(
+        &self,
+        success: bool,
+        exit_code: Option<i32>,
+        signal: Option<i32>,
+    ) -> ExitReport {
+        ExitReport {
+            run: self.run_count,
+            exited_at: Utc::now().timestamp() as u64,
+            success,
+            exit_code,
+            signal,
+            stderr_tail: self.stderr_tail.iter().cloned().collect(),
+        }
+    }
+
+    fn mark_stopped_manually(&mut self) {
+        debug_assert!(
+            self.status == JobStatus::Stopping,
+            "mark_stopped_manually called, but job status was {:?}",
+            self.status
+        );
+
+        // One-shot jobs move to Completed so they dont start again
+        self.status = if self.settings.is_recurring() {
+            JobStatus::Idle
+        } else {
+            JobStatus::Completed
+        };
+        self.process = None;
+        self.recording = None;
+        self.skip_missed_cron_runs();
+        self.last_exit = Some(self.exit_report(true, None, None));
+        self.push_event(JobEvent::StoppedManually);
+
+        if let Some(settings) = self.pending_settings.take() {
+            self.apply_settings(settings);
+        }
+    }
+
+    /// A cron run that fired while the previous run was still recording is skipped,
+    /// instead of starting the moment the previous run exits.
+    fn skip_missed_cron_runs(&mut self) {
+        let now = Utc::now().timestamp() as u64;
+
+        if self.settings.cron.is_some() && self.next_run_start.is_some_and(|next| next < now) {
+            self.next_run_start = self.settings.upcoming_runs(now, 1).first().copied();
+        }
+    }
+}
+
+pub const UPCOMING_RUN_COUNT: usize = 5;
+
+// Caps how far ahead conflicts are predicted for jobs with very short intervals
+const MAX_PLANNED_RUNS: usize = 10_000;
+
+/// Where a queued job stands in line.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct QueueInfo {
+    position: usize,              // 1 == next to start
+    estimated_start: Option<u64>, // Unix, None if every slot is held by a run without duration
+}
+
+/// Estimates when each queued job starts, in queue order. `slots` holds when each slot frees
+/// up (None == never), `durations` the duration of each queued job.
+pub fn estimate_queue(mut slots: Vec<Option<u64>>, durations: &[u16], now: u64) -> Vec<QueueInfo> {
+    durations
+        .iter()
+        .enumerate()
+        .map(|(i, &duration)| {
+            let earliest = slots
+                .iter_mut()
+                .filter(|slot| slot.is_some())
+                .min_by_key(|slot| slot.unwrap_or(u64::MAX));
+
+            let estimated_start = earliest.map(|slot| {
+                let start = slot.unwrap_or_default().max(now);
+                *slot = match duration {
+                    0 => None,
+                    duration => Some(start + duration as u64),
+                };
+                start
+            });
+
+            QueueInfo {
+                position: i + 1,
+                estimated_start,
+            }
+        })
+        .collect()
+}
+
+#[derive(Serialize, Clone)]
+pub struct JobInfo {
+    job_id: u32,
+    job_uid: String,
+    status: JobStatus,
+    started_at: Option<u64>,
+    next_run_start: Option<u64>,
+    upcoming_runs: Vec<u64>,
+    run_count: u32,
+    last_exit: Option<ExitReport>,
+    retry: RetryState,
+    logs: Logs,
+    settings: RecorderSettings,
+    pending_settings: Option<RecorderSettings>,
+    queue: Option<QueueInfo>, // Only set while the job is queued
+}
+
+impl JobInfo {
+    pub fn with_queue(mut self, queue: Option<QueueInfo>) -> Self {
+        self.queue = queue;
+        self
+    }
+}
+
+impl From<&Job> for JobInfo {
+    fn from(value: &Job) -> Self {
+        Self {
+            job_id: value.job_id,
+            job_uid: value.job_uid.clone(),
+            status: value.status,
+            started_at: value.started_at,
+            next_run_start: value.next_run_start,
+            upcoming_runs: match value.next_run_start {
+                Some(next) => value.settings.upcoming_runs(next, UPCOMING_RUN_COUNT),
+                None => Vec::new(),
+            },
+            run_count: value.run_count,
+            last_exit: value.last_exit.clone(),
+            retry: value.retry.clone(),
+            logs: value.logs.get_truncated(),
+            settings: value.settings.clone(),
+            pending_settings: value.pending_settings.clone(),
+            queue: None,
+        }
+    }
+}
+
+/// Snapshot of the parts of a `Job` that survive a backend restart.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct JobRecord {
+    job_id: u32,
+    job_uid: String,
+    status: JobStatus,
+    next_run_start: Option<u64>,
+    #[serde(default)]
+    run_count: u32,
+    #[serde(default)]
+    last_exit: Option<ExitReport>,
+    #[serde(default)]
+    retry: RetryState,
+    settings: RecorderSettings,
+    #[serde(default)]
+    pending_settings: Option<RecorderSettings>,
+}
+
+impl JobRecord {
+    pub fn job_id(&self) -> u32 {
+        self.job_id
+    }
+
+    pub fn status(&self) -> JobStatus {
+        self.status
+    }
+}
+
+impl From<&Job> for JobRecord {
+    fn from(value: &Job) -> Self {
+        Self {
+            job_id: value.job_id,
+            job_uid: value.job_uid.clone(),
+            status: value.status,
+            next_run_start: value.next_run_start,
+            run_count: value.run_count,
+            last_exit: value.last_exit.clone(),
+            retry: value.retry.clone(),
+            settings: value.settings.clone(),
+            pending_settings: value.pending_settings.clone(),
+        }
+    }
+}
+
+fn new_log_sender() -> broadcast::Sender<Log> {
+    // Streams that fall further behind than this skip the missed lines
+    const LOG_CHANNEL_CAPACITY: usize = 256;
+
+    broadcast::channel(LOG_CHANNEL_CAPACITY).0
+}
+
+/// Whether the scheduler may start a job in this status. A failed recurring job keeps
+/// its schedule, a failed one-shot job stays failed.
+fn is_schedulable(status: JobStatus, settings: &RecorderSettings) -> bool {
+    match status {
+        JobStatus::Idle => true,
+        JobStatus::Failed { .. } => settings.is_recurring(),
+        _ => false,
+    }
+}
+
+/// Asks the recorder to finish its files with SIGINT, and kills it if it hasn't exited after
+/// `grace_period`. Returns true if it exited on its own.
+async fn terminate(child: &mut Child, grace_period: Duration) -> io::Result<bool> {
+    // No id means the child was already reaped
+    let Some(pid) = child.id() else {
+        return Ok(true);
+    };
+
+    match kill(Pid::from_raw(pid as i32), Signal::SIGINT) {
+        Ok(()) | Err(Errno::ESRCH) => {}
+        Err(err) => return Err(err.into()),
+    }
+
+    match timeout(grace_period, child.wait()).await {
+        Ok(exit_status) => exit_status.map(|_| true),
+        Err(..) => child.kill().await.map(|()| false),
+    }
+}
+
+/// Moves `next` forward in steps of `interval` until it is no longer in the past,
+/// so a restored job keeps its original cadence instead of firing immediately.
+fn next_run_after(next: u64, interval: u64, now: u64) -> u64 {
+    if next >= now {
+        return next;
+    }
+
+    let missed = (now - next).div_ceil(interval);
+    next + missed * interval
+}
+
+pub fn to_scientific(num: u32) -> String {
+    if num == 0 {
+        return "0e0".to_string();
+    }
+
+    let s = num.to_string();
+    let exponent = s.len() - 1;
+
+    let mut mantissa = s[..1].to_string();
+
+    if s.len() > 1 {
+        let rest = &s[1..];
+        let trimmed = &rest[..rest.len().min(3)];
+        if !trimmed.chars().all(|c| c == '0') {
+            mantissa.push('d');
+            mantissa.push_str(trimmed);
+            mantissa = mantissa.trim_end_matches('0').to_string();
+        }
+    }
+
+    format!("{}e{}", mantissa, exponent)
+}
+
+/// Inverse of `to_scientific`. Precision lost by `to_scientific` is not recovered.
+pub fn from_scientific(s: &str) -> Option<u32> {
+    let (mantissa, exponent) = s.split_once('e')?;
+    let exponent: u32 = exponent.parse().ok()?;
+
+    let (integer, fraction) = mantissa.split_once('d').unwrap_or((mantissa, ""));
+    if integer.len() != 1 || !fraction.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+
+    let digits: u32 = format!("{}{}", integer, fraction).parse().ok()?;
+    let shift = exponent.checked_sub(fraction.len() as u32)?;
+
+    digits.checked_mul(10u32.checked_pow(shift)?)
+}
+
+pub fn generate_uid() -> String {
+    const LENGTH: usize = 9;
+    const CHARSET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
+    let mut rng = thread_rng();
+
+    (0..LENGTH)
+        .map(|i| {
+            let idx = rng.gen_range(0..CHARSET.len());
+            let char_val = CHARSET[idx] as char;
+            if i > 0 && (i + 1) % 5 == 0 {
+                '-'
+            } else {
+                char_val
+            }
+        })
+        .collect::<String>()
+}
+
+fn get_next_free_id(map: &HashMap<u32, SharedJob>) -> u32 {
+    (u32::MIN..u32::MAX)
+        .find(|&id| !map.contains_key(&id))
+        .expect("Job ID space exhausted")
+}
+
+pub async fn restore_jobs(records: Vec<JobRecord>, shared_job_map: SharedJobMap) -> usize {
+    let mut hashmap = shared_job_map.lock().await;
+    let mut restored = 0;
+
+    for record in records {
+        let job = Job::restore(record);
+        if hashmap
+            .insert(job.id(), Arc::new(Mutex::new(job)))
+            .is_none()
+        {
+            restored += 1;
+        }
+    }
+
+    restored
+}
+
+pub async fn create_job(settings: RecorderSettings, shared_job_map: SharedJobMap) -> SharedJob {
+    let mut hashmap = shared_job_map.lock().await;
+    let job_id: u32 = get_next_free_id(&hashmap);
+
+    let job = Job::new(job_id, settings);
+
+    let shared_job: SharedJob = Arc::new(Mutex::new(job));
+
+    hashmap.insert(job_id, shared_job.clone());
+
+    shared_job
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod recorder_settings {
+        use super::*;
+        use crate::kiwi::Secret;
+
+        #[test]
+        fn zoom_too_high() {
+            let settings = RecorderSettings::new(RecordingType::PNG, 10_000_000, 32, 10, None);
+            assert!(matches!(
+                settings.validate(),
+                Err(RecorderSettingsError::ZoomTooHigh)
+            ));
+        }
+
+        #[test]
+        fn zoom_max_boundary() {
+            let settings = RecorderSettings::new(RecordingType::PNG, 15_000_000, 31, 10, None);
+            assert!(settings.validate().is_ok());
+        }
+
+        #[test]
+        fn zoom_min_boundary() {
+            let settings = RecorderSettings::new(RecordingType::PNG, 15_000_000, 0, 10, None);
+            assert!(settings.validate().is_ok());
+        }
+
+        #[test]
+        fn frequency_above_max() {
+            let settings = RecorderSettings::new(RecordingType::PNG, 16_681_359, 0, 10, None);
+            assert!(matches!(
+                settings.validate(),
+                Err(RecorderSettingsError::FrequencyAboveMax)
+            ));
+        }
+
+        #[test]
+        fn frequency_below_min() {
+            let settings = RecorderSettings::new(RecordingType::PNG, 147_500, 2, 10, None);
+            assert!(matches!(
+                settings.validate(),
+                Err(RecorderSettingsError::FrequencyBelowMin)
+            ));
+        }
+
+        #[test]
+        fn frequency_within_bounds() {
+            let settings = RecorderSettings::new(RecordingType::PNG, 15_000_000, 0, 10, None);
+            assert!(settings.validate().is_ok());
+        }
+
+        #[test]
+        fn filename_format_png() {
+            let settings = RecorderSettings::new(RecordingType::PNG, 947_500, 10, 10, None);
+            let filename = settings.get_filename("UID123");
+            assert!(filename.contains("UID123"));
+            assert!(filename.contains("Fq9d475e5")); // scientific format
+            assert!(filename.contains("Zm10")); // zoom included
+        }
+
+        #[test]
+        fn filename_format_iq() {
+            let settings = RecorderSettings::new(RecordingType::IQ, 16_490_000, 2, 10, None);
+            let filename = settings.get_filename("UID123");
+            assert!(filename.contains("UID123"));
+            assert!(filename.contains("Fq1d649e7"));
+            assert!(filename.contains("Bw1d2e4"));
+        }
+
+        #[test]
+        fn cron_valid() {
+            let settings = RecorderSettings::new(RecordingType::PNG, 15_000_000, 0, 10, None)
+                .with_cron("0 6 * * *");
+            assert!(settings.validate().is_ok());
+            assert!(settings.is_recurring());
+        }
+
+        #[test]
+        fn cron_invalid() {
+            let settings = RecorderSettings::new(RecordingType::PNG, 15_000_000, 0, 10, None)
+                .with_cron("at dawn");
+            assert!(matches!(
+                settings.validate(),
+                Err(RecorderSettingsError::InvalidCron(..))
+            ));
+        }
+
+        #[test]
+        fn cron_and_interval_conflict() {
+            let settings = RecorderSettings::new(RecordingType::PNG, 15_000_000, 0, 10, Some(60))
+                .with_cron("0 6 * * *");
+            assert!(matches!(
+                settings.validate(),
+                Err(RecorderSettingsError::ConflictingSchedules)
+            ));
+        }
+
+        #[test]
+        fn next_run_start_interval() {
+            let settings = RecorderSettings::new(RecordingType::PNG, 15_000_000, 0, 10, Some(60));
+            assert_eq!(settings.next_run_start(1000), Some(1060));
+        }
+
+        #[test]
+        fn next_run_start_cron() {
+            // 2025-01-01 00:00:00 UTC
+            const NEW_YEAR: u64 = 1_735_689_600;
+            let settings = RecorderSettings::new(RecordingType::PNG, 15_000_000, 0, 10, None)
+                .with_cron("0 * * * *");
+            assert_eq!(settings.next_run_start(NEW_YEAR), Some(NEW_YEAR + 3600));
+        }
+
+        #[test]
+        fn upcoming_runs_interval() {
+            let settings = RecorderSettings::new(RecordingType::PNG, 15_000_000, 0, 10, Some(60));
+            assert_eq!(settings.upcoming_runs(1000, 3), vec![1000, 1060, 1120]);
+        }
+
+        #[test]
+        fn upcoming_runs_once() {
+            let settings = RecorderSettings::new(RecordingType::PNG, 15_000_000, 0, 10, None);
+            assert!(settings.upcoming_runs(1000, 3).is_empty());
+        }
+
+        #[test]
+        fn planned_runs_interval() {
+            let settings = RecorderSettings::new(RecordingType::PNG, 15_000_000, 0, 10, Some(60));
+            assert_eq!(
+                settings.planned_runs(1000, 1125),
+                vec![(1000, 1010), (1060, 1070), (1120, 1125)]
+            );
+        }
+
+        #[test]
+        fn planned_runs_once_without_duration() {
+            let settings = RecorderSettings::new(RecordingType::PNG, 15_000_000, 0, 0, None);
+            assert_eq!(settings.planned_runs(1000, 5000), vec![(1000, 5000)]);
+            assert!(settings.planned_runs(5000, 5000).is_empty());
+        }
+
+        fn receiver() -> KiwiReceiver {
+            Config::default().receivers().remove(0)
+        }
+
+        #[test]
+        fn as_args_png() {
+            let settings = RecorderSettings::new(RecordingType::PNG, 10_000_000, 5, 10, None);
+            let args = settings.as_args("UID123", &receiver(), &RecorderConfig::default());
+            assert!(args.contains(&"--wf".to_string()));
+            assert!(args.contains(&"--wf-png".to_string()));
+            assert!(args.contains(&"--zoom=5".to_string()));
+        }
+
+        #[test]
+        fn as_args_iq() {
+            let settings = RecorderSettings::new(RecordingType::IQ, 10_000_000, 0, 10, None);
+            let args = settings.as_args("UID123", &receiver(), &RecorderConfig::default());
+            assert!(args.contains(&"--kiwi-wav".to_string()));
+            assert!(args.contains(&"--modulation=iq".to_string()));
+        }
+
+        #[test]
+        fn as_args_receiver() {
+            let receiver = KiwiReceiver {
+                name: "roof".into(),
+                host: "10.0.0.21".into(),
+                port: 8074,
+                password: None,
+                tlimit_password: None,
+                channels: 4,
+            };
+            let settings = RecorderSettings::new(RecordingType::PNG, 10_000_000, 5, 10, None)
+                .with_receiver("roof");
+            let args = settings.as_args("UID123", &receiver, &RecorderConfig::default());
+            assert_eq!(args[..4], ["-s", "10.0.0.21", "-p", "8074"]);
+            assert!(!args.iter().any(|arg| arg.starts_with("--password")));
+
+            let receiver = KiwiReceiver {
+                password: Some(Secret::new("hunter2")),
+                ..receiver
+            };
+            let args = settings.as_args("UID123", &receiver, &RecorderConfig::default());
+            assert!(args.contains(&"--password=hunter2".to_string()));
+        }
+
+        #[test]
+        fn iq_ignores_zoom() {
+            let settings = RecorderSettings::new(RecordingType::IQ, 10_000_000, 0, 10, None);
+            assert!(settings.validate().is_ok());
+        }
+
+        #[test]
+        fn iq_invalid_passband() {
+            for passband in [(3000, -3000), (-7000, 0), (0, 7000)] {
+                let settings = RecorderSettings::new(RecordingType::IQ, 10_000_000, 0, 10, None)
+                    .with_iq(IqSettings::new(Some(passband), true));
+                assert!(matches!(
+                    settings.validate(),
+                    Err(RecorderSettingsError::InvalidIqSettings(..))
+                ));
+            }
+        }
+
+        #[test]
+        fn iq_passband_outside_band() {
+            let settings = RecorderSettings::new(RecordingType::IQ, 29_998_000, 0, 10, None);
+            assert!(matches!(
+                settings.validate(),
+                Err(RecorderSettingsError::FrequencyAboveMax)
+            ));
+
+            let settings = settings.with_iq(IqSettings::new(Some((-5000, 1000)), true));
+            assert!(settings.validate().is_ok());
+        }
+
+        #[test]
+        fn filename_format_iq_passband() {
+            let settings = RecorderSettings::new(RecordingType::IQ, 16_490_000, 0, 10, None)
+                .with_iq(IqSettings::new(Some((-2500, 2500)), true));
+            assert!(settings.get_filename("UID123").ends_with("_Bw5e3"));
+        }
+
+        #[test]
+        fn as_args_iq_settings() {
+            let settings = RecorderSettings::new(RecordingType::IQ, 10_000_000, 0, 10, None)
+                .with_iq(IqSettings::new(Some((-2500, 2500)), false));
+            let args = settings.as_args("UID123", &receiver(), &RecorderConfig::default());
+            assert!(args.contains(&"--modulation=iq".to_string()));
+            assert!(args.contains(&"--lp-cutoff=-2500".to_string()));
+            assert!(args.contains(&"--hp-cutoff=2500".to_string()));
+            assert!(!args.contains(&"--kiwi-wav".to_string()));
+        }
+
+        fn audio(modulation: Modulation, passband: Option<(i32, i32)>) -> RecorderSettings {
+            RecorderSettings::new(RecordingType::Audio, 14_200_000, 0, 10, None)
+                .with_audio(AudioSettings::new(modulation, passband, None, None))
+        }
+
+        #[test]
+        fn audio_valid() {
+            assert!(audio(Modulation::AM, None).validate().is_ok());
+            assert!(audio(Modulation::USB, Some((300, 3000))).validate().is_ok());
+            assert!(
+                audio(Modulation::LSB, Some((-3000, -300)))
+                    .validate()
+                    .is_ok()
+            );
+        }
+
+        #[test]
+        fn audio_missing_settings() {
+            let settings = RecorderSettings::new(RecordingType::Audio, 14_200_000, 0, 10, None);
+            assert!(matches!(
+                settings.validate(),
+                Err(RecorderSettingsError::MissingAudioSettings)
+            ));
+        }
+
+        #[test]
+        fn audio_invalid_passband() {
+            for passband in [(3000, 300), (-7000, 0), (0, 7000)] {
+                assert!(matches!(
+                    audio(Modulation::AM, Some(passband)).validate(),
+                    Err(RecorderSettingsError::InvalidAudioSettings(..))
+                ));
+            }
+            assert!(
+                audio(Modulation::USB, Some((-300, 3000)))
+                    .validate()
+                    .is_err()
+            );
+            assert!(
+                audio(Modulation::LSB, Some((-3000, 300)))
+                    .validate()
+                    .is_err()
+            );
+        }
+
+        #[test]
+        fn audio_passband_outside_band() {
+            let settings =
+                RecorderSettings::new(RecordingType::Audio, 1_000, 0, 10, None).with_audio(
+                    AudioSettings::new(Modulation::LSB, Some((-3000, -300)), None, None),
+                );
+            assert!(matches!(
+                settings.validate(),
+                Err(RecorderSettingsError::FrequencyBelowMin)
+            ));
+        }
+
+        #[test]
+        fn audio_without_passband_outside_band() {
+            let settings = RecorderSettings::new(RecordingType::Audio, 99_000_000, 0, 10, None)
+                .with_audio(AudioSettings::new(Modulation::AM, None, None, None));
+            assert!(matches!(
+                settings.validate(),
+                Err(RecorderSettingsError::FrequencyAboveMax)
+            ));
+        }
+
+        #[test]
+        fn audio_gain_and_squelch_limits() {
+            let settings = RecorderSettings::new(RecordingType::Audio, 14_200_000, 0, 10, None)
+                .with_audio(AudioSettings::new(Modulation::NBFM, None, Some(121), None));
+            assert!(settings.validate().is_err());
+
+            let settings = RecorderSettings::new(RecordingType::Audio, 14_200_000, 0, 10, None)
+                .with_audio(AudioSettings::new(Modulation::NBFM, None, None, Some(100)));
+            assert!(settings.validate().is_err());
+        }
+
+        #[test]
+        fn filename_format_audio() {
+            let filename = audio(Modulation::USB, Some((300, 3000))).get_filename("UID123");
+            assert!(filename.contains("Fq1d42e7"));
+            assert!(filename.ends_with("_Mdusb_Bw2d7e3"));
+
+            let filename = audio(Modulation::CW, None).get_filename("UID123");
+            assert!(filename.ends_with("_Mdcw"));
+        }
+
+        #[test]
+        fn as_args_audio() {
+            let settings =
+                RecorderSettings::new(RecordingType::Audio, 14_200_000, 0, 10, None).with_audio(
+                    AudioSettings::new(Modulation::USB, Some((300, 3000)), Some(50), Some(10)),
+                );
+            let args = settings.as_args("UID123", &receiver(), &RecorderConfig::default());
+            assert!(args.contains(&"--modulation=usb".to_string()));
+            assert!(args.contains(&"--lp-cutoff=300".to_string()));
+            assert!(args.contains(&"--hp-cutoff=3000".to_string()));
+            assert!(args.contains(&"--agc-gain=50".to_string()));
+            assert!(args.contains(&"--squelch-threshold=10".to_string()));
+            assert!(!args.contains(&"--wf".to_string()));
+        }
+    }
+
+    mod job_record {
+        use super::*;
+
+        fn record(status: JobStatus, interval: Option<u32>, next: Option<u64>) -> JobRecord {
+            JobRecord {
+                job_id: 7,
+                job_uid: "ABCD-EFGH".into(),
+                status,
+                next_run_start: next,
+                run_count: 0,
+                last_exit: None,
+                retry: RetryState::default(),
+                settings: RecorderSettings::new(RecordingType::PNG, 10_000_000, 5, 10, interval),
+                pending_settings: None,
+            }
+        }
+
+        #[test]
+        fn restore_keeps_identity() {
+            let job = Job::restore(record(JobStatus::Idle, Some(60), None));
+            assert_eq!(job.job_id, 7);
+            assert_eq!(job.job_uid, "ABCD-EFGH");
+            assert!(job.process.is_none());
+        }
+
+        #[test]
+        fn restore_running_interval_job_is_idle() {
+            let job = Job::restore(record(JobStatus::Running, Some(60), None));
+            assert_eq!(job.status, JobStatus::Idle);
+        }
+
+        #[test]
+        fn restore_running_one_shot_job_is_completed() {
+            let job = Job::restore(record(JobStatus::Running, None, None));
+            assert_eq!(job.status, JobStatus::Completed);
+        }
+
+        #[test]
+        fn restore_future_run_is_unchanged() {
+            let next = Utc::now().timestamp() as u64 + 1000;
+            let job = Job::restore(record(JobStatus::Idle, Some(60), Some(next)));
+            assert_eq!(job.next_run_start, Some(next));
+        }
+
+        #[test]
+        fn next_run_after_keeps_cadence() {
+            assert_eq!(next_run_after(100, 60, 100), 100);
+            assert_eq!(next_run_after(100, 60, 101), 160);
+            assert_eq!(next_run_after(100, 60, 160), 160);
+            assert_eq!(next_run_after(100, 60, 281), 340);
+        }
+    }
+
+    mod job_exit {
+        use super::*;
+
+        fn running_job(interval: Option<u32>) -> Job {
+            let settings = RecorderSettings::new(RecordingType::PNG, 10_000_000, 5, 10, interval);
+            let mut job = Job::new(0, settings);
+            job.status = JobStatus::Running;
+            job.run_count = 1;
+            job
+        }
+
+        #[test]
+        fn success_completes_one_shot() {
+            let mut job = running_job(None);
+            job.mark_exited(ExitStatus::from_raw(0));
+
+            assert_eq!(job.status, JobStatus::Completed);
+            assert!(job.last_exit.unwrap().success);
+        }
+
+        #[test]
+        fn success_idles_recurring() {
+            let mut job = running_job(Some(60));
+            job.mark_exited(ExitStatus::from_raw(0));
+
+            assert_eq!(job.status, JobStatus::Idle);
+        }
+
+        #[test]
+        fn exit_code_fails() {
+            let mut job = running_job(None);
+            job.push_stderr_tail("Too many users".into());
+            job.mark_exited(ExitStatus::from_raw(1 << 8));
+
+            assert_eq!(
+                job.status,
+                JobStatus::Failed {
+                    exit_code: Some(1),
+                    signal: None
+                }
+            );
+            let report = job.last_exit.unwrap();
+            assert!(!report.success);
+            assert_eq!(report.run, 1);
+            assert_eq!(report.stderr_tail, vec!["Too many users".to_string()]);
+        }
+
+        #[test]
+        fn signal_fails() {
+            let mut job = running_job(None);
+            job.mark_exited(ExitStatus::from_raw(9));
+
+            assert_eq!(
+                job.status,
+                JobStatus::Failed {
+                    exit_code: None,
+                    signal: Some(9)
+                }
+            );
+        }
+
+        #[test]
+        fn failed_recurring_is_schedulable() {
+            let mut job = running_job(Some(60));
+            job.mark_exited(ExitStatus::from_raw(1 << 8));
+
+            assert!(is_schedulable(job.status, &job.settings));
+        }
+
+        #[test]
+        fn failed_one_shot_is_not_schedulable() {
+            let mut job = running_job(None);
+            job.mark_exited(ExitStatus::from_raw(1 << 8));
+
+            assert!(!is_schedulable(job.status, &job.settings));
+        }
+
+        #[test]
+        fn stderr_tail_keeps_last_lines() {
+            let mut job = running_job(None);
+            for i in 0..15 {
+                job.push_stderr_tail(i.to_string());
+            }
+
+            assert_eq!(job.stderr_tail.len(), 10);
+            assert_eq!(job.stderr_tail.front().unwrap(), "5");
+        }
+
+        #[test]
+        fn subscribe_logs_replays_and_follows() {
+            let mut job = running_job(None);
+            job.push_log("before".into());
+
+            let (backlog, mut receiver) = job.subscribe_logs();
+            job.mark_exited(ExitStatus::from_raw(0));
+
+            assert_eq!(backlog.last().unwrap().data, "before");
+            assert_eq!(receiver.try_recv().unwrap().data, "<Exited> exit status: 0");
+        }
+
+        #[test]
+        fn lifecycle_is_logged_as_events() {
+            let mut job = running_job(None);
+            job.mark_exited(ExitStatus::from_raw(9));
+
+            let log = job.logs.iter().last().unwrap();
+            assert_eq!(log.data, "<Exited> signal: 9");
+            assert_eq!(
+                log.event(),
+                Some(&JobEvent::Exited {
+                    success: false,
+                    exit_code: None,
+                    signal: Some(9)
+                })
+            );
+        }
+
+        #[test]
+        fn event_serializes_with_type_tag() {
+            let log = Log::from_event(100, JobEvent::Retried { attempt: 2 });
+            assert_eq!(
+                serde_json::to_value(&log).unwrap(),
+                serde_json::json!({
+                    "timestamp": 100,
+                    "data": "<Retry> Attempt 2",
+                    "event": { "type": "Retried", "attempt": 2 }
+                })
+            );
+
+            let log = Log::new(100, "<STDOUT> connected".into());
+            assert_eq!(
+                serde_json::to_value(&log).unwrap()["event"],
+                serde_json::Value::Null
+            );
+        }
+    }
+
+    mod update_settings {
+        use super::*;
+
+        fn settings(interval: Option<u32>) -> RecorderSettings {
+            RecorderSettings::new(RecordingType::PNG, 10_000_000, 5, 10, interval)
+        }
+
+        #[test]
+        fn idle_job_applies_immediately() {
+            let mut job = Job::new(0, settings(Some(60)));
+            job.started_at = Some(1000);
+
+            let restart = job.update_settings(settings(Some(120)), ApplyMode::NextRun);
+
+            assert!(!restart.unwrap());
+            assert_eq!(job.settings.interval, Some(120));
+            let now = Utc::now().timestamp() as u64;
+            assert!(job.next_run_start.unwrap() >= now);
+            assert!(matches!(
+                job.logs.iter().last().unwrap().event(),
+                Some(JobEvent::SettingsChanged { .. })
+            ));
+        }
+
+        #[test]
+        fn interval_keeps_last_start() {
+            let now = Utc::now().timestamp() as u64;
+            let mut job = Job::new(0, settings(Some(60)));
+            job.started_at = Some(now);
+
+            job.update_settings(settings(Some(3600)), ApplyMode::Now)
+                .unwrap();
+
+            assert_eq!(job.next_run_start, Some(now + 3600));
+        }
+
+        #[test]
+        fn completed_job_becomes_recurring() {
+            let mut job = Job::new(0, settings(None));
+            job.status = JobStatus::Completed;
+            job.run_count = 1;
+
+            job.update_settings(settings(Some(60)), ApplyMode::NextRun)
+                .unwrap();
+
+            assert_eq!(job.status, JobStatus::Idle);
+            assert!(job.next_run_start.is_some());
+        }
+
+        #[test]
+        fn running_job_waits_for_next_run() {
+            let mut job = Job::new(0, settings(None));
+            job.status = JobStatus::Running;
+
+            let restart = job.update_settings(settings(Some(60)), ApplyMode::NextRun);
+
+            assert!(!restart.unwrap());
+            assert_eq!(job.settings.interval, None);
+            assert!(job.pending_settings.is_some());
+
+            job.mark_exited(ExitStatus::from_raw(0));
+
+            assert_eq!(job.settings.interval, Some(60));
+            assert_eq!(job.status, JobStatus::Idle);
+            assert!(job.pending_settings.is_none());
         }
-        if responsible_for_exit {
-            let mut state: MutexGuard<'_, Job> = job.lock().await;
-            state.mark_exited();
+
+        #[test]
+        fn running_job_restarts_now() {
+            let mut job = Job::new(0, settings(None));
+            job.status = JobStatus::Running;
+
+            let restart = job.update_settings(settings(Some(60)), ApplyMode::Now);
+
+            assert!(restart.unwrap());
+            assert!(job.pending_settings.is_none());
         }
-    }
 
-    fn push_log(&mut self, data: String) {
-        self.logs.push(Log {
-            timestamp: Utc::now().timestamp() as u64,
-            data,
-        });
+        #[test]
+        fn stopping_job_is_busy() {
+            let mut job = Job::new(0, settings(None));
+            job.status = JobStatus::Stopping;
+
+            let err = job
+                .update_settings(settings(Some(60)), ApplyMode::Now)
+                .unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::ResourceBusy);
+        }
     }
 
-    fn mark_starting(&mut self) -> io::Result<()> {
-        debug_assert!(self.process.is_none());
+    mod pause {
+        use super::*;
 
-        if self.status != JobStatus::Idle {
-            return Err(io::Error::other("Job not idle"));
+        fn interval_job() -> Job {
+            let settings = RecorderSettings::new(RecordingType::PNG, 10_000_000, 5, 10, Some(60));
+            Job::new(0, settings)
         }
 
-        self.status = JobStatus::Starting;
-        Ok(())
-    }
+        #[tokio::test]
+        async fn paused_job_is_not_started() {
+            let shared_job = Arc::new(Mutex::new(interval_job()));
+            shared_job.lock().await.next_run_start = Some(0);
 
-    fn mark_running(&mut self, process: Child) {
-        debug_assert!(self.status == JobStatus::Starting);
-        let now = Utc::now().timestamp() as u64;
+            Job::pause(shared_job.clone(), &Config::default())
+                .await
+                .unwrap();
 
-        self.status = JobStatus::Running;
-        self.process = Some(process);
-        self.started_at = Some(now);
-        self.next_run_start = match self.settings.interval {
-            Some(0) | None => None,
-            Some(interval) => Some(now + interval as u64),
-        };
-        self.push_log("<Started>".to_string());
-        self.push_log(format!("<Settings>  {}", self.settings))
-    }
+            let job = shared_job.lock().await;
+            assert_eq!(job.status, JobStatus::Paused);
+            assert_eq!(job.next_run_start, None);
+            assert!(!job.is_waiting_to_start());
+        }
 
-    fn mark_stopping(&mut self) -> io::Result<()> {
-        if self.status != JobStatus::Running {
-            return Err(io::Error::other("Job is not running"));
+        #[tokio::test]
+        async fn one_shot_job_cant_be_paused() {
+            let settings = RecorderSettings::new(RecordingType::PNG, 10_000_000, 5, 10, None);
+            let shared_job = Arc::new(Mutex::new(Job::new(0, settings)));
+
+            let err = Job::pause(shared_job, &Config::default())
+                .await
+                .unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
         }
 
-        self.status = JobStatus::Stopping;
-        Ok(())
-    }
+        #[test]
+        fn pause_clears_pending_retry() {
+            let mut job = interval_job();
+            job.status = JobStatus::Failed {
+                exit_code: Some(1),
+                signal: None,
+            };
+            job.retry.retry_at = Some(0);
 
-    fn mark_exited(&mut self) {
-        debug_assert!(
-            self.status == JobStatus::Running || self.status == JobStatus::Stopping,
-            "mark_exited called, but job status was {:?}",
-            self.status
-        );
+            job.mark_paused();
 
-        // One-shot jobs move to Completed so they dont start again
-        self.status = if self.settings.interval.is_none() {
-            JobStatus::Completed
-        } else {
-            JobStatus::Idle
-        };
-        self.process = None;
-        self.push_log("<Exited>".to_string());
-    }
+            assert!(!job.can_start());
+        }
 
-    fn mark_stopped_manually(&mut self) {
-        debug_assert!(
-            self.status == JobStatus::Stopping,
-            "mark_stopped_manually called, but job status was {:?}",
-            self.status
-        );
+        #[test]
+        fn resume_skips_missed_runs() {
+            let now = Utc::now().timestamp() as u64;
+            let mut job = interval_job();
+            job.started_at = Some(now - 3600);
+            job.mark_paused();
 
-        // One-shot jobs move to Completed so they dont start again
-        self.status = if self.settings.interval.is_none() {
-            JobStatus::Completed
-        } else {
-            JobStatus::Idle
-        };
-        self.process = None;
-        self.push_log("<Stopped Manually>".to_string());
-    }
-}
+            job.resume().unwrap();
 
-#[derive(Serialize, Clone)]
-pub struct JobInfo {
-    job_id: u32,
-    job_uid: String,
-    status: JobStatus,
-    started_at: Option<u64>,
-    next_run_start: Option<u64>,
-    logs: Logs,
-    settings: RecorderSettings,
-}
+            assert_eq!(job.status, JobStatus::Idle);
+            let next = job.next_run_start.unwrap();
+            assert!(next >= now && next <= now + 1);
+        }
 
-impl From<&Job> for JobInfo {
-    fn from(value: &Job) -> Self {
-        Self {
-            job_id: value.job_id,
-            job_uid: value.job_uid.clone(),
-            status: value.status,
-            started_at: value.started_at,
-            next_run_start: value.next_run_start,
-            logs: value.logs.get_truncated(),
-            settings: value.settings,
+        #[test]
+        fn resume_requires_paused() {
+            let mut job = interval_job();
+            assert!(job.resume().is_err());
         }
-    }
-}
 
-pub fn to_scientific(num: u32) -> String {
-    if num == 0 {
-        return "0e0".to_string();
+        #[test]
+        fn settings_change_keeps_paused() {
+            let mut job = interval_job();
+            job.mark_paused();
+
+            let settings = RecorderSettings::new(RecordingType::PNG, 10_000_000, 5, 10, None)
+                .with_cron("0 6 * * *");
+            job.update_settings(settings, ApplyMode::Now).unwrap();
+
+            assert_eq!(job.status, JobStatus::Paused);
+            assert_eq!(job.next_run_start, None);
+        }
     }
 
-    let s = num.to_string();
-    let exponent = s.len() - 1;
+    mod queue {
+        use super::*;
 
-    let mut mantissa = s[..1].to_string();
+        #[test]
+        fn queued_job_is_waiting_to_start() {
+            let settings = RecorderSettings::new(RecordingType::PNG, 10_000_000, 5, 10, None);
+            let mut job = Job::new(0, settings);
+            job.queue();
 
-    if s.len() > 1 {
-        let rest = &s[1..];
-        let trimmed = &rest[..rest.len().min(3)];
-        if !trimmed.chars().all(|c| c == '0') {
-            mantissa.push('d');
-            mantissa.push_str(trimmed);
-            mantissa = mantissa.trim_end_matches('0').to_string();
+            assert_eq!(job.status, JobStatus::Queued);
+            assert!(!job.occupies_slot());
+            assert!(job.is_waiting_to_start());
         }
-    }
 
-    format!("{}e{}", mantissa, exponent)
-}
+        #[test]
+        fn estimate_uses_earliest_slot() {
+            let queue = estimate_queue(vec![Some(200), Some(100)], &[50, 10, 10], 0);
+            let starts: Vec<Option<u64>> = queue.iter().map(|info| info.estimated_start).collect();
+            assert_eq!(starts, vec![Some(100), Some(150), Some(160)]);
+            assert_eq!(queue[2].position, 3);
+        }
 
-pub fn generate_uid() -> String {
-    const LENGTH: usize = 9;
-    const CHARSET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
-    let mut rng = thread_rng();
+        #[test]
+        fn estimate_free_slot_starts_now() {
+            let queue = estimate_queue(vec![Some(0)], &[10], 1000);
+            assert_eq!(queue[0].estimated_start, Some(1000));
+        }
 
-    (0..LENGTH)
-        .map(|i| {
-            let idx = rng.gen_range(0..CHARSET.len());
-            let char_val = CHARSET[idx] as char;
-            if i > 0 && (i + 1) % 5 == 0 {
-                '-'
-            } else {
-                char_val
-            }
-        })
-        .collect::<String>()
-}
+        #[test]
+        fn estimate_unknown_behind_endless_runs() {
+            let queue = estimate_queue(vec![Some(100), None], &[0, 10], 0);
+            let starts: Vec<Option<u64>> = queue.iter().map(|info| info.estimated_start).collect();
+            assert_eq!(starts, vec![Some(100), None]);
+        }
+    }
 
-fn get_next_free_id(map: &HashMap<u32, SharedJob>) -> u32 {
-    (u32::MIN..u32::MAX)
-        .find(|&id| !map.contains_key(&id))
-        .expect("Job ID space exhausted")
-}
+    mod preempt {
+        use super::*;
 
-pub async fn create_job(settings: RecorderSettings, shared_job_map: SharedJobMap) -> SharedJob {
-    let mut hashmap = shared_job_map.lock().await;
-    let job_id: u32 = get_next_free_id(&hashmap);
+        #[tokio::test]
+        async fn preempted_job_is_queued() {
+            let settings = RecorderSettings::new(RecordingType::PNG, 10_000_000, 5, 10, None);
+            let mut job = Job::new(0, settings);
+            job.status = JobStatus::Running;
+            let shared_job = Arc::new(Mutex::new(job));
+
+            Job::preempt(shared_job.clone(), 1, &Config::default())
+                .await
+                .unwrap();
+
+            let job = shared_job.lock().await;
+            assert_eq!(job.status, JobStatus::Queued);
+            assert!(job.is_waiting_to_start());
+            assert_eq!(
+                job.logs.iter().last().unwrap().event(),
+                Some(&JobEvent::Preempted { by_job_id: 1 })
+            );
+        }
 
-    let job = Job::new(job_id, settings);
+        #[tokio::test]
+        async fn preempted_job_keeps_its_schedule() {
+            let settings = RecorderSettings::new(RecordingType::PNG, 10_000_000, 5, 10, Some(3600));
+            let mut job = Job::new(0, settings);
+            job.status = JobStatus::Running;
+            let scheduled = Utc::now().timestamp() as u64 + 1800;
+            job.next_run_start = Some(scheduled);
+            let shared_job = Arc::new(Mutex::new(job));
+
+            Job::preempt(shared_job.clone(), 1, &Config::default())
+                .await
+                .unwrap();
+
+            // Making up for the cut short run leaves the next regular run where it was
+            let mut job = shared_job.lock().await;
+            assert_eq!(job.status, JobStatus::Queued);
+            assert!(job.mark_starting().is_ok());
+            let child = tokio::process::Command::new("true").spawn().unwrap();
+            job.mark_running(child, "station".into());
+            assert_eq!(job.next_run_start, Some(scheduled));
+        }
+    }
 
-    let shared_job: SharedJob = Arc::new(Mutex::new(job));
+    mod terminate {
+        use super::*;
 
-    hashmap.insert(job_id, shared_job.clone());
+        fn spawn(script: &str) -> Child {
+            tokio::process::Command::new("sh")
+                .args(["-c", script])
+                .spawn()
+                .unwrap()
+        }
 
-    shared_job
-}
+        #[tokio::test]
+        async fn exits_on_sigint() {
+            let mut child = spawn("exec sleep 30");
+            assert!(terminate(&mut child, Duration::from_secs(5)).await.unwrap());
+        }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+        #[tokio::test]
+        async fn killed_after_grace_period() {
+            let mut child = spawn("trap '' INT; exec sleep 30");
+            // Give the shell time to ignore SIGINT before it is sent
+            sleep(Duration::from_millis(100)).await;
+
+            let graceful = terminate(&mut child, Duration::from_millis(200))
+                .await
+                .unwrap();
+            assert!(!graceful);
+            assert!(child.try_wait().unwrap().is_some());
+        }
+    }
 
-    mod recorder_settings {
+    mod run_now {
         use super::*;
 
-        #[test]
-        fn zoom_too_high() {
-            let settings = RecorderSettings::new(RecordingType::PNG, 10_000_000, 32, 10, None);
-            assert!(matches!(
-                settings.validate(),
-                Err(RecorderSettingsError::ZoomTooHigh)
-            ));
+        #[tokio::test]
+        async fn running_job_is_refused() {
+            let settings = RecorderSettings::new(RecordingType::PNG, 10_000_000, 5, 10, Some(60));
+            let mut job = Job::new(0, settings);
+            job.status = JobStatus::Running;
+
+            let err = Job::run_now(
+                Arc::new(Mutex::new(job)),
+                RunNowOptions::default(),
+                &Config::default(),
+            )
+            .await
+            .unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
         }
+    }
 
-        #[test]
-        fn zoom_max_boundary() {
-            let settings = RecorderSettings::new(RecordingType::PNG, 15_000_000, 31, 10, None);
-            assert!(settings.validate().is_ok());
+    mod retry {
+        use super::*;
+
+        fn failed_job(interval: Option<u32>, policy: RetryPolicy) -> Job {
+            let settings = RecorderSettings::new(RecordingType::IQ, 10_000_000, 0, 10, interval)
+                .with_retry(policy);
+            let mut job = Job::new(0, settings);
+            job.status = JobStatus::Running;
+            job.run_count = 1;
+            job.mark_exited(ExitStatus::from_raw(1 << 8));
+            job
         }
 
         #[test]
-        fn zoom_min_boundary() {
-            let settings = RecorderSettings::new(RecordingType::PNG, 15_000_000, 0, 10, None);
-            assert!(settings.validate().is_ok());
+        fn delay_backs_off() {
+            let policy = RetryPolicy::new(5, 10, 2.0);
+            assert_eq!(policy.delay(1), 10);
+            assert_eq!(policy.delay(2), 20);
+            assert_eq!(policy.delay(3), 40);
         }
 
         #[test]
-        fn frequency_above_max() {
-            let settings = RecorderSettings::new(RecordingType::PNG, 16_681_359, 0, 10, None);
-            assert!(matches!(
-                settings.validate(),
-                Err(RecorderSettingsError::FrequencyAboveMax)
-            ));
+        fn delay_is_capped() {
+            let policy = RetryPolicy::new(100, 60, 10.0);
+            assert_eq!(policy.delay(50), 24 * 60 * 60);
         }
 
         #[test]
-        fn frequency_below_min() {
-            let settings = RecorderSettings::new(RecordingType::PNG, 147_500, 2, 10, None);
+        fn backoff_below_one_is_invalid() {
+            let settings = RecorderSettings::new(RecordingType::IQ, 10_000_000, 0, 10, None)
+                .with_retry(RetryPolicy::new(3, 10, 0.5));
             assert!(matches!(
                 settings.validate(),
-                Err(RecorderSettingsError::FrequencyBelowMin)
+                Err(RecorderSettingsError::InvalidRetryPolicy(..))
             ));
         }
 
         #[test]
-        fn frequency_within_bounds() {
-            let settings = RecorderSettings::new(RecordingType::PNG, 15_000_000, 0, 10, None);
-            assert!(settings.validate().is_ok());
+        fn failure_schedules_retry() {
+            let job = failed_job(None, RetryPolicy::new(3, 30, 2.0));
+            let now = Utc::now().timestamp() as u64;
+
+            assert_eq!(job.retry.attempt, 1);
+            let retry_at = job.retry.retry_at.unwrap();
+            assert!(retry_at >= now + 29 && retry_at <= now + 30);
+            assert_eq!(job.retry.history.len(), 1);
+            assert!(job.can_start());
+            assert!(!job.is_waiting_to_start());
         }
 
         #[test]
-        fn filename_format_png() {
-            let settings = RecorderSettings::new(RecordingType::PNG, 947_500, 10, 10, None);
-            let filename = settings.get_filename("UID123");
-            assert!(filename.contains("UID123"));
-            assert!(filename.contains("Fq9d475e5")); // scientific format
-            assert!(filename.contains("Zm10")); // zoom included
+        fn due_retry_is_waiting_to_start() {
+            let mut job = failed_job(None, RetryPolicy::new(3, 0, 2.0));
+            job.retry.retry_at = Some(0);
+            assert!(job.is_waiting_to_start());
         }
 
         #[test]
-        fn filename_format_iq() {
-            let settings = RecorderSettings::new(RecordingType::IQ, 16_490_000, 2, 10, None);
-            let filename = settings.get_filename("UID123");
-            assert!(filename.contains("UID123"));
-            assert!(filename.contains("Fq1d649e7"));
-            assert!(filename.contains("Bw1d2e4"));
+        fn retries_exhaust() {
+            let mut job = failed_job(None, RetryPolicy::new(1, 0, 2.0));
+            job.status = JobStatus::Running;
+            job.retry.retry_at = None;
+            job.mark_exited(ExitStatus::from_raw(1 << 8));
+
+            assert_eq!(job.retry.retry_at, None);
+            assert_eq!(job.retry.history.back().unwrap().retry_at, None);
+            assert!(!job.can_start());
         }
 
         #[test]
-        fn as_args_png() {
-            let settings = RecorderSettings::new(RecordingType::PNG, 10_000_000, 5, 10, None);
-            let args = settings.as_args("UID123");
-            assert!(args.contains(&"--wf".to_string()));
-            assert!(args.contains(&"--wf-png".to_string()));
-            assert!(args.contains(&"--zoom=5".to_string()));
+        fn success_resets_attempts() {
+            let mut job = failed_job(None, RetryPolicy::new(3, 0, 2.0));
+            job.status = JobStatus::Running;
+            job.retry.retry_at = None;
+            job.mark_exited(ExitStatus::from_raw(0));
+
+            assert_eq!(job.retry.attempt, 0);
+            assert_eq!(job.status, JobStatus::Completed);
         }
 
         #[test]
-        fn as_args_iq() {
+        fn no_policy_no_retry() {
             let settings = RecorderSettings::new(RecordingType::IQ, 10_000_000, 0, 10, None);
-            let args = settings.as_args("UID123");
-            assert!(args.contains(&"--kiwi-wav".to_string()));
-            assert!(args.contains(&"--modulation=iq".to_string()));
+            let mut job = Job::new(0, settings);
+            job.status = JobStatus::Running;
+            job.mark_exited(ExitStatus::from_raw(1 << 8));
+
+            assert_eq!(job.retry, RetryState::default());
+        }
+
+        #[test]
+        fn due_at_prefers_earliest() {
+            let mut job = failed_job(Some(3600), RetryPolicy::new(3, 30, 2.0));
+            job.next_run_start = Some(u64::MAX - 1);
+            assert_eq!(job.due_at(), job.retry.retry_at);
         }
     }
 
@@ -616,10 +3126,12 @@ mod tests {
             let input = Log {
                 timestamp: 147_000,
                 data: "Lorem ipsum dolor sit amet, consectetur adipiscing elit. Aliquam ultrices scelerisque mi, eget molestie ipsum vestibulum tristique. Nulla vitae mi.".into(),
+                event: None,
             };
             let output = Log {
                 timestamp: 147_000,
                 data: "Lorem ipsum dolor sit amet, consectetur adipiscing elit. Aliquam ultrices scelerisque mi, eget molestie ipsum vestibulum tristique. Nulla vitae mi.".into(),
+                event: None,
             };
 
             assert_eq!(input.get_truncated(), output)
@@ -630,10 +3142,12 @@ mod tests {
             let input = Log {
                 timestamp: 200_000,
                 data: "Lorem ipsum dolor sit amet, consectetur adipiscing elit. Curabitur lacinia congue dui at euismod. Etiam sed lorem sit amet odio sollicitudin feugiat. Phasellus risus leo, fermentum et posuere at orci.".into(),
+                event: None,
             };
             let output = Log {
                 timestamp: 200_000,
                 data: "Lorem ipsum dolor sit amet, consectetur adipiscing elit. Curabitur lacinia congue dui at euismod. Etiam sed lorem sit amet odio sollicitudin feugiat. Phasellus risus leo, fermentum et posuere at orci.".into(),
+                event: None,
             };
 
             assert_eq!(input.get_truncated(), output)
@@ -646,10 +3160,12 @@ mod tests {
                 data: "Lorem ipsum dolor sit amet, consectetur adipiscing elit. Maecenas sodales ligula leo, sed tempus velit vehicula eget. Morbi orci eros, commodo cursus sapien vitae, fringilla blandit orci. Praesent eget 
                     velit id velit sollicitudin elementum. Proin sed efficitur dolor, quis porta nulla. Pellentesque ac libero sed tortor tempor tincidunt. Integer condimentum risus sed ipsum tempus feugiat. Nulla lacinia velit 
                     sed nunc vulputate, at sagittis mi convallis. Donec ultrices, metus nec rhoncus porttitor.".into(),
+                event: None,
             };
             let output = Log {
                 timestamp: 500_000,
                 data: "Lorem ipsum dolor sit amet, consectetur adipiscing elit. Maecenas sodales ligula leo, sed tempus velit vehicula eget. Morbi orci eros, commodo cursus sapien vitae, fringilla blandit orci. Praesent ege...".into(),
+                event: None,
             };
 
             assert_eq!(input.get_truncated(), output)
@@ -661,10 +3177,12 @@ mod tests {
             let input = Log {
                 timestamp: 500_000,
                 data: "Lorem ipsum dolor sit amet, consectetur adipiscing elit. Sed molestie hendrerit scelerisque. Varius natoque penatibus et magnis dis parturient montes, nascetur ridiculus mus. A multibyte UTF-8 char: 𒈙, right at the border.".into(),
+                event: None,
             };
             let output = Log {
                 timestamp: 500_000,
                 data: "Lorem ipsum dolor sit amet, consectetur adipiscing elit. Sed molestie hendrerit scelerisque. Varius natoque penatibus et magnis dis parturient montes, nascetur ridiculus mus. A multibyte UTF-8 char: 𒈙...".into(),
+                event: None,
             };
 
             assert_eq!(input.get_truncated(), output)
@@ -681,10 +3199,12 @@ mod tests {
                     Log {
                         timestamp: 123_456,
                         data: "a".into(),
+                        event: None,
                     },
                     Log {
                         timestamp: 654_321,
                         data: "b".into(),
+                        event: None,
                     },
                 ]
                 .into(),
@@ -694,10 +3214,12 @@ mod tests {
             logs.push(Log {
                 timestamp: 123_456,
                 data: "a".into(),
+                event: None,
             });
             logs.push(Log {
                 timestamp: 654_321,
                 data: "b".into(),
+                event: None,
             });
 
             assert_eq!(logs, target);
@@ -711,6 +3233,7 @@ mod tests {
                 logs.push(Log {
                     timestamp: i,
                     data: i.to_string(),
+                    event: None,
                 });
             }
 
@@ -729,6 +3252,7 @@ mod tests {
                 data.push_back(Log {
                     timestamp: i,
                     data: i.to_string(),
+                    event: None,
                 });
             }
 
@@ -748,6 +3272,7 @@ mod tests {
             data.push_back(Log {
                 timestamp: 69_420,
                 data: "x".repeat(10_000),
+                event: None,
             });
 
             let logs = Logs::new(data);
@@ -758,6 +3283,78 @@ mod tests {
         }
     }
 
+    mod log_query {
+        use super::*;
+
+        fn logs() -> Logs {
+            Logs::new(
+                [
+                    Log::from_event(100, JobEvent::Restored),
+                    Log::new(101, "<STDOUT> connected".into()),
+                    Log::new(102, format!("<STDERR> {}", "x".repeat(300))),
+                    Log::from_event(103, JobEvent::StoppedManually),
+                ]
+                .into(),
+            )
+        }
+
+        #[test]
+        fn source_from_tag() {
+            let sources: Vec<LogSource> = logs().iter().map(Log::source).collect();
+            assert_eq!(
+                sources,
+                vec![
+                    LogSource::Event,
+                    LogSource::Stdout,
+                    LogSource::Stderr,
+                    LogSource::Event
+                ]
+            );
+        }
+
+        #[test]
+        fn filter_by_since_and_pipe() {
+            let query = LogQuery {
+                since: Some(101),
+                pipe: Some(LogSource::Event),
+                ..Default::default()
+            };
+            let page = query.apply(&logs());
+
+            assert_eq!(page.total, 1);
+            assert_eq!(page.logs[0].timestamp, 103);
+        }
+
+        #[test]
+        fn paginates_oldest_first() {
+            let query = LogQuery {
+                offset: 1,
+                limit: Some(2),
+                ..Default::default()
+            };
+            let page = query.apply(&logs());
+
+            assert_eq!(page.total, 4);
+            let timestamps: Vec<u64> = page.logs.iter().map(|log| log.timestamp).collect();
+            assert_eq!(timestamps, vec![101, 102]);
+        }
+
+        #[test]
+        fn full_skips_truncation() {
+            let query = LogQuery {
+                pipe: Some(LogSource::Stderr),
+                ..Default::default()
+            };
+            assert!(query.apply(&logs()).logs[0].data.ends_with("..."));
+
+            let query = LogQuery {
+                full: true,
+                ..query
+            };
+            assert_eq!(query.apply(&logs()).logs[0].data.len(), 309);
+        }
+    }
+
     mod utils {
         use super::*;
 
@@ -816,5 +3413,31 @@ mod tests {
             let scientific = to_scientific(NUMBER);
             assert_eq!(scientific, OUTPUT);
         }
+
+        #[test]
+        fn from_scientific_1() {
+            assert_eq!(from_scientific("1d475e5"), Some(147_500));
+        }
+
+        #[test]
+        fn from_scientific_2() {
+            assert_eq!(from_scientific("3e7"), Some(30_000_000));
+            assert_eq!(from_scientific("0e0"), Some(0));
+        }
+
+        #[test]
+        fn from_scientific_round_trip() {
+            for num in [0, 7, 10, 147_500, 9_475_000, 30_000_000] {
+                assert_eq!(from_scientific(&to_scientific(num)), Some(num));
+            }
+        }
+
+        #[test]
+        fn from_scientific_invalid() {
+            assert_eq!(from_scientific("1d2345e2"), None);
+            assert_eq!(from_scientific("12e3"), None);
+            assert_eq!(from_scientific("abc"), None);
+            assert_eq!(from_scientific("1d2e"), None);
+        }
     }
 }
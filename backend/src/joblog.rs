@@ -0,0 +1,291 @@
+use crate::job::{Job, Log};
+use chrono::DateTime;
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use tokio::fs::{self, OpenOptions};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::broadcast::{self, error::RecvError};
+
+const LOG_EXTENSION: &str = "log";
+
+#[derive(Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct JobLogConfig {
+    pub dir: PathBuf,
+    pub max_file_bytes: u64, // The active file is rotated once it would grow past this
+    pub max_files: usize,    // Rotated files kept per job uid, the oldest is deleted
+}
+
+impl Default for JobLogConfig {
+    fn default() -> Self {
+        Self {
+            dir: "/var/recorder/logs/".into(),
+            max_file_bytes: 1024 * 1024,
+            max_files: 4,
+        }
+    }
+}
+
+impl JobLogConfig {
+    /// `<uid>.log` for the active file, `<uid>.<n>.log` for rotated ones, 1 being the newest.
+    fn path(&self, job_uid: &str, rotation: usize) -> PathBuf {
+        match rotation {
+            0 => self.dir.join(format!("{}.{}", job_uid, LOG_EXTENSION)),
+            n => self
+                .dir
+                .join(format!("{}.{}.{}", job_uid, n, LOG_EXTENSION)),
+        }
+    }
+}
+
+/// A job's log file, rotated by size.
+pub struct JobLogFile {
+    config: JobLogConfig,
+    job_uid: String,
+    size: Option<u64>, // Size of the active file, None until it's first read
+}
+
+impl JobLogFile {
+    pub fn new(config: JobLogConfig, job_uid: String) -> Self {
+        Self {
+            config,
+            job_uid,
+            size: None,
+        }
+    }
+
+    pub async fn append(&mut self, log: &Log) -> io::Result<()> {
+        let line = format_line(log);
+        let active = self.config.path(&self.job_uid, 0);
+
+        let size = match self.size {
+            Some(size) => size,
+            None => {
+                fs::create_dir_all(&self.config.dir).await?;
+                match fs::metadata(&active).await {
+                    Ok(metadata) => metadata.len(),
+                    Err(err) if err.kind() == io::ErrorKind::NotFound => 0,
+                    Err(err) => return Err(err),
+                }
+            }
+        };
+
+        let size = if size > 0 && size + line.len() as u64 > self.config.max_file_bytes {
+            self.rotate().await?;
+            0
+        } else {
+            size
+        };
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&active)
+            .await?;
+        file.write_all(line.as_bytes()).await?;
+
+        self.size = Some(size + line.len() as u64);
+        Ok(())
+    }
+
+    /// Shifts every file one rotation up, dropping the one that falls off the end.
+    async fn rotate(&self) -> io::Result<()> {
+        let last = self.config.path(&self.job_uid, self.config.max_files);
+        match fs::remove_file(&last).await {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+            _ => {}
+        }
+
+        for rotation in (0..self.config.max_files).rev() {
+            let from = self.config.path(&self.job_uid, rotation);
+            let to = self.config.path(&self.job_uid, rotation + 1);
+            match fs::rename(&from, &to).await {
+                Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+                _ => {}
+            }
+        }
+
+        if self.config.max_files == 0 {
+            fs::remove_file(self.config.path(&self.job_uid, 0)).await?;
+        }
+
+        Ok(())
+    }
+}
+
+fn format_line(log: &Log) -> String {
+    let time = DateTime::from_timestamp(log.timestamp() as i64, 0)
+        .map(|time| time.format("%Y-%m-%d %H:%M:%S UTC").to_string())
+        .unwrap_or_else(|| log.timestamp().to_string());
+
+    format!("{} {}\n", time, log.data())
+}
+
+/// Mirrors a job's logs to its log file, starting with what is already buffered.
+/// Runs until the job is dropped.
+pub fn spawn_log_writer(job: &Job, config: &JobLogConfig) {
+    let (backlog, receiver) = job.subscribe_logs();
+    let file = JobLogFile::new(config.clone(), job.uid().to_string());
+
+    tokio::spawn(write_logs(file, backlog, receiver));
+}
+
+async fn write_logs(
+    mut file: JobLogFile,
+    backlog: Vec<Log>,
+    mut receiver: broadcast::Receiver<Log>,
+) {
+    for log in backlog {
+        if let Err(err) = file.append(&log).await {
+            println!("Error id: r7k2p9xw4mbq1zfe. Error info: {}", err);
+        }
+    }
+
+    loop {
+        let log = match receiver.recv().await {
+            Ok(log) => log,
+            Err(RecvError::Lagged(skipped)) => Log::new(
+                chrono::Utc::now().timestamp() as u64,
+                format!("<Log Writer Lagged> Skipped {} lines", skipped),
+            ),
+            Err(RecvError::Closed) => return,
+        };
+
+        if let Err(err) = file.append(&log).await {
+            println!("Error id: r7k2p9xw4mbq1zfe. Error info: {}", err);
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct LogFileInfo {
+    name: String,
+    job_uid: String,
+    size: u64,     // Bytes
+    modified: u64, // Unix
+}
+
+/// Lists the job log files in `dir`, sorted by name. A missing directory has no logs.
+pub async fn list_log_files(dir: &Path) -> io::Result<Vec<LogFileInfo>> {
+    let mut entries = match fs::read_dir(dir).await {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err),
+    };
+
+    let mut files = Vec::new();
+    while let Some(entry) = entries.next_entry().await? {
+        let metadata = entry.metadata().await?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let Some(stem) = name.strip_suffix(&format!(".{}", LOG_EXTENSION)) else {
+            continue;
+        };
+        if !metadata.is_file() {
+            continue;
+        }
+
+        let modified = metadata
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_secs());
+
+        files.push(LogFileInfo {
+            job_uid: stem.split('.').next().unwrap_or_default().to_string(),
+            name,
+            size: metadata.len(),
+            modified,
+        });
+    }
+    files.sort_by(|a, b| a.name.cmp(&b.name));
+
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::job::generate_uid;
+
+    fn test_config(name: &str, max_file_bytes: u64) -> JobLogConfig {
+        JobLogConfig {
+            dir: std::env::temp_dir().join(format!("kiwi-logs-{}-{}", name, generate_uid())),
+            max_file_bytes,
+            max_files: 2,
+        }
+    }
+
+    fn log(data: &str) -> Log {
+        // 2025-01-01 06:00:00 UTC
+        Log::new(1_735_711_200, data.to_string())
+    }
+
+    #[test]
+    fn line_format() {
+        assert_eq!(
+            format_line(&log("<STDOUT> connected")),
+            "2025-01-01 06:00:00 UTC <STDOUT> connected\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn append_and_list() {
+        let config = test_config("append", 1024);
+        let mut file = JobLogFile::new(config.clone(), "ABCD-EFGH".into());
+
+        file.append(&log("<Started>")).await.unwrap();
+        file.append(&log("<Exited> exit status: 0")).await.unwrap();
+
+        let data = fs::read_to_string(config.path("ABCD-EFGH", 0))
+            .await
+            .unwrap();
+        assert_eq!(data.lines().count(), 2);
+
+        let files = list_log_files(&config.dir).await.unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].name, "ABCD-EFGH.log");
+        assert_eq!(files[0].job_uid, "ABCD-EFGH");
+
+        let _ = fs::remove_dir_all(&config.dir).await;
+    }
+
+    #[tokio::test]
+    async fn rotates_by_size() {
+        // Every line is 33 bytes, so each file holds a single line
+        let config = test_config("rotate", 40);
+        let mut file = JobLogFile::new(config.clone(), "ABCD-EFGH".into());
+
+        for data in ["<Line 1>", "<Line 2>", "<Line 3>", "<Line 4>"] {
+            file.append(&log(data)).await.unwrap();
+        }
+
+        let names: Vec<String> = list_log_files(&config.dir)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|file| file.name)
+            .collect();
+        assert_eq!(
+            names,
+            vec!["ABCD-EFGH.1.log", "ABCD-EFGH.2.log", "ABCD-EFGH.log"]
+        );
+
+        let newest = fs::read_to_string(config.path("ABCD-EFGH", 0))
+            .await
+            .unwrap();
+        assert!(newest.contains("<Line 4>"));
+        let oldest = fs::read_to_string(config.path("ABCD-EFGH", 2))
+            .await
+            .unwrap();
+        assert!(oldest.contains("<Line 2>"));
+
+        let _ = fs::remove_dir_all(&config.dir).await;
+    }
+
+    #[tokio::test]
+    async fn missing_dir_is_empty() {
+        let config = test_config("missing", 1024);
+        assert!(list_log_files(&config.dir).await.unwrap().is_empty());
+    }
+}
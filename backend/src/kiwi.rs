@@ -0,0 +1,407 @@
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt::{self, Debug, Formatter};
+use std::time::Duration;
+use thiserror::Error;
+
+// Name of the receiver made from `[recorder]` when no receivers are configured
+pub const DEFAULT_RECEIVER: &str = "default";
+
+/// A credential that is never printed or serialized, only handed to kiwirecorder.py.
+#[derive(Deserialize, Clone, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn new(secret: impl Into<String>) -> Self {
+        Self(secret.into())
+    }
+
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Debug for Secret {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "Secret(***)")
+    }
+}
+
+/// A KiwiSDR jobs can record from.
+#[derive(Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct KiwiReceiver {
+    pub name: String,
+    pub host: String,
+    #[serde(default = "KiwiReceiver::default_port")]
+    pub port: u16,
+    #[serde(default)]
+    pub password: Option<Secret>, // Needed to connect to a password protected Kiwi
+    #[serde(default)]
+    pub tlimit_password: Option<Secret>, // Exempts recordings from the Kiwi's connection time limit
+    #[serde(default = "KiwiReceiver::default_channels")]
+    pub channels: usize, // Recordings the backend may run on this receiver at once
+}
+
+impl KiwiReceiver {
+    pub fn has_credentials(&self) -> bool {
+        self.password.is_some() || self.tlimit_password.is_some()
+    }
+
+    /// Arguments passing the credentials to kiwirecorder.py. It only takes them on the command
+    /// line, so they show up in `ps` for every local user while a recording runs.
+    pub fn credential_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if let Some(password) = &self.password {
+            args.push(format!("--password={}", password.expose()));
+        }
+        if let Some(password) = &self.tlimit_password {
+            args.push(format!("--tlimit-password={}", password.expose()));
+        }
+
+        args
+    }
+
+    fn default_port() -> u16 {
+        8073
+    }
+
+    fn default_channels() -> usize {
+        4
+    }
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ReceiverConfig {
+    pub check_channels: bool, // Refuse or queue jobs while every channel of the Kiwi is taken
+    pub poll_interval: u64,   // sec
+    pub timeout: u64,         // sec, per request to the Kiwi
+}
+
+impl Default for ReceiverConfig {
+    fn default() -> Self {
+        Self {
+            check_channels: true,
+            poll_interval: 10,
+            timeout: 3,
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum KiwiError {
+    #[error("Could not reach the receiver: {0}")]
+    Request(#[from] reqwest::Error),
+
+    #[error("Invalid receiver status: {0}")]
+    InvalidStatus(String),
+}
+
+/// One receiver channel and who is listening on it.
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct ChannelInfo {
+    channel: u32,
+    in_use: bool,
+    user: Option<String>,
+    frequency: Option<u64>, // Hz
+    mode: Option<String>,
+}
+
+/// What the Kiwi reports about its channels, including the ones taken by web users.
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct ReceiverStatus {
+    name: String,
+    offline: bool,
+    users: usize,
+    users_max: usize,
+    free_channels: usize,
+    channels: Vec<ChannelInfo>, // Empty if the Kiwi doesn't share its user list
+    checked_at: u64,            // Unix
+}
+
+impl ReceiverStatus {
+    pub fn free_channels(&self) -> usize {
+        self.free_channels
+    }
+
+    pub fn checked_at(&self) -> u64 {
+        self.checked_at
+    }
+}
+
+/// A receiver with the channels used by the backend and what the Kiwi last reported.
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct ReceiverInfo {
+    name: String,
+    host: String,
+    port: u16,
+    channels: usize,
+    used_channels: usize, // Held by jobs of this backend
+    status: Option<ReceiverStatus>,
+    error: Option<String>, // Why the Kiwi couldn't be asked
+}
+
+impl ReceiverInfo {
+    pub fn new(
+        receiver: &KiwiReceiver,
+        used_channels: usize,
+        status: Result<ReceiverStatus, KiwiError>,
+    ) -> Self {
+        let (status, error) = match status {
+            Ok(status) => (Some(status), None),
+            Err(err) => (None, Some(err.to_string())),
+        };
+
+        Self {
+            name: receiver.name.clone(),
+            host: receiver.host.clone(),
+            port: receiver.port,
+            channels: receiver.channels,
+            used_channels,
+            status,
+            error,
+        }
+    }
+}
+
+/// Parses the `key=value` lines served on `/status`.
+pub fn parse_status(data: &str, checked_at: u64) -> Result<ReceiverStatus, KiwiError> {
+    let fields: HashMap<&str, &str> = data
+        .lines()
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, value)| (key.trim(), value.trim()))
+        .collect();
+
+    let count = |key: &str| -> Result<usize, KiwiError> {
+        let value = fields
+            .get(key)
+            .ok_or_else(|| KiwiError::InvalidStatus(format!("{} is missing", key)))?;
+        value
+            .parse()
+            .map_err(|_| KiwiError::InvalidStatus(format!("{} is not a number: {}", key, value)))
+    };
+
+    let users = count("users")?;
+    let users_max = count("users_max")?;
+    let offline = fields.get("offline").is_some_and(|value| *value == "yes");
+
+    Ok(ReceiverStatus {
+        name: fields.get("name").unwrap_or(&"").to_string(),
+        offline,
+        users,
+        users_max,
+        free_channels: match offline {
+            true => 0,
+            false => users_max.saturating_sub(users),
+        },
+        channels: Vec::new(),
+        checked_at,
+    })
+}
+
+// Entry of the JSON list served on `/users`, free channels only carry `i`
+#[derive(Deserialize)]
+struct UserEntry {
+    i: u32,
+    n: Option<String>,
+    f: Option<u64>,
+    m: Option<String>,
+}
+
+/// Parses the JSON list of channels served on `/users`.
+pub fn parse_users(data: &str) -> Result<Vec<ChannelInfo>, KiwiError> {
+    let entries: Vec<UserEntry> =
+        serde_json::from_str(data).map_err(|e| KiwiError::InvalidStatus(e.to_string()))?;
+
+    Ok(entries
+        .into_iter()
+        .map(|entry| ChannelInfo {
+            channel: entry.i,
+            in_use: entry.n.is_some(),
+            user: entry.n,
+            frequency: entry.f,
+            mode: entry.m,
+        })
+        .collect())
+}
+
+/// Talks to the web server of a KiwiSDR.
+#[derive(Clone, Debug)]
+pub struct KiwiClient {
+    base_url: String,
+    http: reqwest::Client,
+}
+
+impl KiwiClient {
+    pub fn new(host: &str, port: u16, timeout: Duration) -> Self {
+        let http = reqwest::Client::builder()
+            .timeout(timeout)
+            .build()
+            .expect("Client without TLS always builds");
+
+        Self {
+            base_url: format!("http://{}:{}", host, port),
+            http,
+        }
+    }
+
+    async fn get(&self, path: &str) -> Result<String, KiwiError> {
+        let response = self
+            .http
+            .get(format!("{}{}", self.base_url, path))
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(response.text().await?)
+    }
+
+    /// Fetches `/status`, and the channel list from `/users` if the Kiwi shares it.
+    pub async fn status(&self) -> Result<ReceiverStatus, KiwiError> {
+        let data = self.get("/status").await?;
+        let mut status = parse_status(&data, Utc::now().timestamp() as u64)?;
+
+        match self.get("/users").await.and_then(|data| parse_users(&data)) {
+            Ok(channels) => status.channels = channels,
+            Err(err) => println!("Error id: t6b0xk3mw9jr5neq. Error info: {}", err),
+        }
+
+        Ok(status)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    const STATUS: &str = "status=active\noffline=no\nname=Test Kiwi\nusers=1\nusers_max=4\n";
+    const USERS: &str =
+        r#"[{"i":0,"n":"web%20user","g":"","f":7100000,"m":"lsb","z":0},{"i":1},{"i":2},{"i":3}]"#;
+
+    /// Serves `routes` as `(path, body)` over HTTP on a free local port, 404 for anything else.
+    async fn fake_kiwi(routes: &'static [(&'static str, &'static str)]) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut request = vec![0; 4096];
+                let read = socket.read(&mut request).await.unwrap_or_default();
+                let request = String::from_utf8_lossy(&request[..read]);
+                let path = request.split_whitespace().nth(1).unwrap_or_default();
+
+                let response = match routes.iter().find(|(route, _)| *route == path) {
+                    Some((_, body)) => format!(
+                        "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        body.len(),
+                        body
+                    ),
+                    None => {
+                        "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                            .to_string()
+                    }
+                };
+                let _ = socket.write_all(response.as_bytes()).await;
+            }
+        });
+
+        port
+    }
+
+    fn client(port: u16) -> KiwiClient {
+        KiwiClient::new("127.0.0.1", port, Duration::from_secs(1))
+    }
+
+    fn protected_receiver() -> KiwiReceiver {
+        KiwiReceiver {
+            name: "roof".into(),
+            host: "10.0.0.21".into(),
+            port: 8073,
+            password: Some(Secret::new("hunter2")),
+            tlimit_password: Some(Secret::new("no-limit")),
+            channels: 4,
+        }
+    }
+
+    #[test]
+    fn credentials_become_args() {
+        assert_eq!(
+            protected_receiver().credential_args(),
+            vec!["--password=hunter2", "--tlimit-password=no-limit"]
+        );
+    }
+
+    #[test]
+    fn credentials_are_never_shown() {
+        let receiver = protected_receiver();
+        assert!(!format!("{:?}", receiver).contains("hunter2"));
+
+        let info = ReceiverInfo::new(&receiver, 0, Err(KiwiError::InvalidStatus("".into())));
+        let json = serde_json::to_string(&info).unwrap();
+        assert!(!json.contains("hunter2") && !json.contains("no-limit"));
+    }
+
+    #[test]
+    fn status_counts_free_channels() {
+        let status = parse_status(STATUS, 0).unwrap();
+        assert_eq!(status.name, "Test Kiwi");
+        assert_eq!(status.users_max, 4);
+        assert_eq!(status.free_channels(), 3);
+    }
+
+    #[test]
+    fn offline_receiver_has_no_free_channels() {
+        let status = parse_status("offline=yes\nusers=0\nusers_max=4\n", 0).unwrap();
+        assert_eq!(status.free_channels(), 0);
+    }
+
+    #[test]
+    fn status_without_users_is_invalid() {
+        assert!(parse_status("status=active\n", 0).is_err());
+        assert!(parse_status("users=many\nusers_max=4\n", 0).is_err());
+    }
+
+    #[test]
+    fn users_mark_taken_channels() {
+        let channels = parse_users(USERS).unwrap();
+        assert_eq!(channels.len(), 4);
+        assert!(channels[0].in_use);
+        assert_eq!(channels[0].frequency, Some(7_100_000));
+        assert!(!channels[1].in_use);
+    }
+
+    #[tokio::test]
+    async fn fetches_status_and_users() {
+        let port = fake_kiwi(&[("/status", STATUS), ("/users", USERS)]).await;
+
+        let status = client(port).status().await.unwrap();
+        assert_eq!(status.free_channels(), 3);
+        assert_eq!(status.channels.len(), 4);
+    }
+
+    #[tokio::test]
+    async fn missing_user_list_is_tolerated() {
+        let port = fake_kiwi(&[("/status", STATUS)]).await;
+
+        let status = client(port).status().await.unwrap();
+        assert_eq!(status.free_channels(), 3);
+        assert!(status.channels.is_empty());
+    }
+
+    #[tokio::test]
+    async fn unreachable_receiver_fails() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        drop(listener);
+
+        assert!(matches!(
+            client(port).status().await,
+            Err(KiwiError::Request(..))
+        ));
+    }
+}
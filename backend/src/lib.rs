@@ -1,4 +1,12 @@
 pub mod api;
+pub mod config;
 pub mod error;
+pub mod files;
 pub mod job;
+pub mod joblog;
+pub mod kiwi;
+pub mod retention;
+pub mod schedule;
+pub mod sse;
 pub mod state;
+pub mod store;
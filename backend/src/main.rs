@@ -1,61 +1,163 @@
 use actix_web::{App, HttpServer, web};
-use std::io::Result;
+use chrono::Utc;
+use clap::Parser;
+use std::io::{Error, Result};
+use std::pin::pin;
 use tokio::{
+    join, select,
+    signal::unix::{SignalKind, signal},
     spawn,
     time::{Duration, sleep},
 };
 
 use backend::api;
+use backend::config::*;
 use backend::job::*;
 use backend::state::*;
 
 #[actix_web::main]
 async fn main() -> Result<()> {
-    let port: u16 = 5004;
+    let cli = Cli::parse();
+    let config = Config::load(cli.config.as_deref()).map_err(Error::other)?;
+    let bind_address = (config.bind_address.clone(), config.port);
 
-    let state: AppState = AppState::default();
+    let state: AppState = AppState::new(config);
+
+    println!("Restoring jobs from {}", state.store.path().display());
+    match state.restore_jobs().await {
+        Ok(count) => println!("Restored {} jobs", count),
+        Err(err) => println!("Error id: 8fh3kd02jvn4mz7q. Error info: {}", err),
+    };
 
     println!("Starting Job Scheduler");
     spawn(job_scheduler(state.clone()));
 
-    println!("Starting server on port {}", port);
-    HttpServer::new(move || {
+    println!("Starting Retention Task");
+    spawn(retention_task(state.clone()));
+
+    if state.config.receiver.check_channels {
+        println!("Starting Receiver Poller");
+        spawn(receiver_poller(state.clone()));
+    }
+
+    println!("Starting server on {}:{}", bind_address.0, bind_address.1);
+    let app_state = state.clone();
+    let server = HttpServer::new(move || {
         App::new()
-            .app_data(web::Data::new(state.clone()))
+            .app_data(web::Data::new(app_state.clone()))
             .configure(api::init_routes)
     })
-    .bind(("0.0.0.0", port))?
-    .run()
-    .await
+    .bind(bind_address)?
+    .disable_signals()
+    .shutdown_timeout(HTTP_SHUTDOWN_TIMEOUT)
+    .run();
+    let handle = server.handle();
+    let mut server = pin!(server);
+
+    select! {
+        result = &mut server => return result,
+        signal = shutdown_signal() => signal?,
+    }
+
+    println!("Shutting down");
+    // The server only finishes stopping while it is polled
+    let http = async {
+        let ((), result) = join!(handle.stop(true), &mut server);
+        if let Err(err) = result {
+            println!("Error id: t6b1xw9qe3km7hzn. Error info: {}", err);
+        }
+    };
+    state.shut_down(http).await;
+    println!("Shutdown complete");
+
+    Ok(())
+}
+
+// sec in flight requests get to finish on shutdown, log streams are cut after it
+const HTTP_SHUTDOWN_TIMEOUT: u64 = 5;
+
+/// Resolves on SIGTERM from systemd or SIGINT from a terminal.
+async fn shutdown_signal() -> Result<()> {
+    let mut terminate = signal(SignalKind::terminate())?;
+    let mut interrupt = signal(SignalKind::interrupt())?;
+
+    select! {
+        _ = terminate.recv() => {}
+        _ = interrupt.recv() => {}
+    }
+
+    Ok(())
 }
 
 async fn job_scheduler(state: AppState) {
     println!("Job Scheduler Started Successfully");
     const CHECK_INTERVAL: Duration = Duration::from_secs(1);
     loop {
-        let mut jobs_to_start: Vec<SharedJob> = Vec::new();
-        let shared_jobs: Vec<SharedJob> = {
-            let hashmap = state.jobs.lock().await;
-            hashmap.values().cloned().collect()
+        let StartPlan { preempt, start } = match state.accepts_jobs() {
+            true => state.plan_starts().await,
+            false => StartPlan::default(),
         };
 
-        for shared_job in shared_jobs {
-            let job = shared_job.lock().await;
-
-            if job.is_waiting_to_start() {
-                jobs_to_start.push(shared_job.clone());
-            }
+        // Stopping a run can take up to stop_grace_period, so it happens in the background.
+        // The jobs it makes room for start on a later tick, once the slot is free.
+        let mut making_room = Vec::new();
+        for (job, by_job_id) in preempt {
+            making_room.push(by_job_id);
+            state.preempt_in_background(job, by_job_id).await;
         }
 
-        println!("Jobs to start: {:?}", jobs_to_start);
+        println!("Jobs to start: {:?}", start);
 
-        for job in jobs_to_start {
-            match Job::start(job).await {
+        for job in start {
+            if making_room.contains(&job.lock().await.id()) {
+                continue;
+            }
+            match Job::start(job, &state.config).await {
                 Ok(..) => {}
                 Err(err) => println!("Error id: joi8u4398thn98yg9fddogih. Error info: {}", err),
             };
         }
 
+        if let Err(err) = state.persist_jobs().await {
+            println!("Error id: q2w8vn3ks0dl5xe1. Error info: {}", err);
+        }
+
         sleep(CHECK_INTERVAL).await;
     }
 }
+
+async fn retention_task(state: AppState) {
+    println!("Retention Task Started Successfully");
+    const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+    loop {
+        let active = state.active_recordings().await;
+        let now = Utc::now().timestamp() as u64;
+
+        let config = &state.config;
+        match config
+            .retention
+            .enforce(&config.recorder.output_dir, &active, now)
+            .await
+        {
+            Ok(deleted) if !deleted.is_empty() => {
+                println!("Retention deleted {} files: {:?}", deleted.len(), deleted)
+            }
+            Ok(..) => {}
+            Err(err) => println!("Error id: c5n1wq8rhz3ty6vb. Error info: {}", err),
+        };
+
+        sleep(CHECK_INTERVAL).await;
+    }
+}
+
+async fn receiver_poller(state: AppState) {
+    println!("Receiver Poller Started Successfully");
+    let check_interval = Duration::from_secs(state.config.receiver.poll_interval.max(1));
+    loop {
+        for err in state.refresh_receivers().await {
+            println!("Error id: p0d5ga9mj2ve7rsu. Error info: {}", err);
+        }
+
+        sleep(check_interval).await;
+    }
+}
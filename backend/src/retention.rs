@@ -0,0 +1,236 @@
+use crate::files::*;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::io;
+use std::path::Path;
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetentionPolicy {
+    pub max_total_bytes: Option<u64>, // None == no limit
+    pub max_job_bytes: Option<u64>,   // Per job uid, None == no limit
+    pub max_age: Option<u64>,         // sec, None == keep forever
+    pub min_free_bytes: u64,          // New runs are refused below this
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        Self {
+            max_total_bytes: None,
+            max_job_bytes: None,
+            max_age: None,
+            min_free_bytes: 256 * 1024 * 1024,
+        }
+    }
+}
+
+impl RetentionPolicy {
+    /// Picks the files to delete so the remaining ones satisfy the policy, oldest first.
+    /// Protected files are never picked, but still count towards the size limits.
+    pub fn select_expired(
+        &self,
+        mut files: Vec<RecordingFile>,
+        active: &[String],
+        now: u64,
+    ) -> Vec<RecordingFile> {
+        files.sort_by_key(|file| file.time());
+
+        let mut expired = vec![false; files.len()];
+        let deletable = |file: &RecordingFile| !is_protected(file.name(), active);
+
+        if let Some(max_age) = self.max_age {
+            for (i, file) in files.iter().enumerate() {
+                if file.time().saturating_add(max_age) < now && deletable(file) {
+                    expired[i] = true;
+                }
+            }
+        }
+
+        if let Some(max_job_bytes) = self.max_job_bytes {
+            let mut job_bytes: HashMap<&str, u64> = HashMap::new();
+            for (i, file) in files.iter().enumerate() {
+                if let Some(job_uid) = file.job_uid()
+                    && !expired[i]
+                {
+                    *job_bytes.entry(job_uid).or_default() += file.size();
+                }
+            }
+
+            for (i, file) in files.iter().enumerate() {
+                let Some(job_uid) = file.job_uid() else {
+                    continue;
+                };
+                let bytes = job_bytes.get_mut(job_uid).expect("counted above");
+                if !expired[i] && *bytes > max_job_bytes && deletable(file) {
+                    *bytes -= file.size();
+                    expired[i] = true;
+                }
+            }
+        }
+
+        if let Some(max_total_bytes) = self.max_total_bytes {
+            let mut total_bytes: u64 = files
+                .iter()
+                .zip(&expired)
+                .filter(|(_, expired)| !**expired)
+                .map(|(file, _)| file.size())
+                .sum();
+
+            for (i, file) in files.iter().enumerate() {
+                if total_bytes <= max_total_bytes {
+                    break;
+                }
+                if !expired[i] && deletable(file) {
+                    total_bytes -= file.size();
+                    expired[i] = true;
+                }
+            }
+        }
+
+        files
+            .into_iter()
+            .zip(expired)
+            .filter_map(|(file, expired)| expired.then_some(file))
+            .collect()
+    }
+
+    /// Deletes expired files in `dir`, returning the names of the deleted files.
+    pub async fn enforce(
+        &self,
+        dir: &Path,
+        active: &[String],
+        now: u64,
+    ) -> io::Result<Vec<String>> {
+        let files = list_recordings(dir).await?;
+
+        let mut deleted = Vec::new();
+        for file in self.select_expired(files, active, now) {
+            match delete_recording(&dir.join(file.name())).await {
+                Ok(()) => deleted.push(file.name().to_string()),
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {} // Deleted meanwhile
+                Err(err) => return Err(err),
+            }
+        }
+
+        Ok(deleted)
+    }
+}
+
+/// Bytes available to unprivileged users on the filesystem holding `dir`.
+pub fn free_space(dir: &Path) -> io::Result<u64> {
+    let stat = nix::sys::statvfs::statvfs(dir)?;
+
+    Ok(stat.blocks_available() as u64 * stat.fragment_size() as u64)
+}
+
+/// False only if `dir` is known to have less than `min_free_bytes` available.
+pub fn has_free_space(dir: &Path, min_free_bytes: u64) -> bool {
+    match free_space(dir) {
+        Ok(free) => free >= min_free_bytes,
+        Err(err) => {
+            println!("Error id: 7tg2mbq9zr4kx0wd. Error info: {}", err);
+            true
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2025-01-01 06:00:00 UTC
+    const SIX_AM: u64 = 1_735_711_200;
+    const HOUR: u64 = 3600;
+
+    fn file(uid: &str, hour: u64, size: u64) -> RecordingFile {
+        RecordingFile::new(
+            format!(
+                "KiwiRec_{}_2025-01-01_{:02}-00-00_UTC_Fq1d42e7_Zm10.png",
+                uid, hour
+            ),
+            size,
+            0,
+        )
+    }
+
+    fn names(files: &[RecordingFile]) -> Vec<&str> {
+        files.iter().map(|file| file.name()).collect()
+    }
+
+    #[test]
+    fn default_deletes_nothing() {
+        let files = vec![file("AAAA-AAAA", 1, 100), file("AAAA-AAAA", 2, 100)];
+        let expired = RetentionPolicy::default().select_expired(files, &[], SIX_AM);
+        assert!(expired.is_empty());
+    }
+
+    #[test]
+    fn max_age() {
+        let policy = RetentionPolicy {
+            max_age: Some(2 * HOUR),
+            ..Default::default()
+        };
+        let files = vec![file("AAAA-AAAA", 3, 1), file("AAAA-AAAA", 5, 1)];
+
+        let expired = policy.select_expired(files, &[], SIX_AM);
+        assert_eq!(names(&expired), vec![file("AAAA-AAAA", 3, 1).name()]);
+    }
+
+    #[test]
+    fn max_total_deletes_oldest_first() {
+        let policy = RetentionPolicy {
+            max_total_bytes: Some(250),
+            ..Default::default()
+        };
+        let files = vec![
+            file("AAAA-AAAA", 3, 100),
+            file("BBBB-BBBB", 1, 100),
+            file("AAAA-AAAA", 2, 100),
+            file("BBBB-BBBB", 4, 100),
+        ];
+
+        let expired = policy.select_expired(files, &[], SIX_AM);
+        assert_eq!(
+            names(&expired),
+            vec![
+                file("BBBB-BBBB", 1, 100).name(),
+                file("AAAA-AAAA", 2, 100).name()
+            ]
+        );
+    }
+
+    #[test]
+    fn max_job_bytes_only_touches_large_jobs() {
+        let policy = RetentionPolicy {
+            max_job_bytes: Some(150),
+            ..Default::default()
+        };
+        let files = vec![
+            file("AAAA-AAAA", 1, 100),
+            file("AAAA-AAAA", 2, 100),
+            file("BBBB-BBBB", 0, 100),
+        ];
+
+        let expired = policy.select_expired(files, &[], SIX_AM);
+        assert_eq!(names(&expired), vec![file("AAAA-AAAA", 1, 100).name()]);
+    }
+
+    #[test]
+    fn protected_files_are_kept() {
+        let policy = RetentionPolicy {
+            max_total_bytes: Some(0),
+            ..Default::default()
+        };
+        let files = vec![file("AAAA-AAAA", 1, 100), file("BBBB-BBBB", 2, 100)];
+        let active = vec!["BBBB-BBBB_2025-01-01_02-00-00".to_string()];
+
+        let expired = policy.select_expired(files, &active, SIX_AM);
+        assert_eq!(names(&expired), vec![file("AAAA-AAAA", 1, 100).name()]);
+    }
+
+    #[test]
+    fn free_space_of_temp_dir() {
+        assert!(free_space(&std::env::temp_dir()).is_ok());
+        assert!(has_free_space(&std::env::temp_dir(), 0));
+    }
+}
@@ -0,0 +1,305 @@
+use chrono::{DateTime, Utc};
+use cron::Schedule;
+use serde::Serialize;
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+// Conflicts listed in an error message, the rest are only counted
+const DESCRIBED_CONFLICTS: usize = 3;
+
+/// Parses a cron expression evaluated in UTC.
+///
+/// Accepts the classic 5 field form (`min hour dom month dow`) as well as the
+/// 6 and 7 field forms with leading seconds and trailing year.
+pub fn parse_cron(expression: &str) -> Result<Schedule, String> {
+    let expression = expression.trim();
+    let normalized = match expression.split_whitespace().count() {
+        5 => format!("0 {}", expression),
+        _ => expression.to_string(),
+    };
+
+    Schedule::from_str(&normalized).map_err(|e| e.to_string())
+}
+
+/// Returns up to `count` fire times strictly after the unix timestamp `after`.
+pub fn cron_runs_after(schedule: &Schedule, after: u64, count: usize) -> Vec<u64> {
+    let Some(after) = DateTime::<Utc>::from_timestamp(after as i64, 0) else {
+        return Vec::new();
+    };
+
+    schedule
+        .after(&after)
+        .take(count)
+        .map(|time| time.timestamp() as u64)
+        .collect()
+}
+
+/// Returns the fire times strictly after `after` and before `until`, at most `limit` of them.
+pub fn cron_runs_between(schedule: &Schedule, after: u64, until: u64, limit: usize) -> Vec<u64> {
+    let Some(after) = DateTime::<Utc>::from_timestamp(after as i64, 0) else {
+        return Vec::new();
+    };
+
+    schedule
+        .after(&after)
+        .map(|time| time.timestamp() as u64)
+        .take_while(|&time| time < until)
+        .take(limit)
+        .collect()
+}
+
+/// A predicted run of a job, `job_id` is None for a job that doesn't exist yet.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PlannedRun {
+    pub job_id: Option<u32>,
+    pub start: u64, // Unix
+    pub end: u64,   // Unix, exclusive
+}
+
+/// A stretch of time where a new job would push usage past the available slots.
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct ScheduleConflict {
+    pub start: u64,        // Unix
+    pub end: u64,          // Unix, exclusive
+    pub job_ids: Vec<u32>, // Existing jobs recording alongside the new one
+}
+
+impl Display for ScheduleConflict {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let job_ids: Vec<String> = self.job_ids.iter().map(u32::to_string).collect();
+        write!(
+            f,
+            "{} to {} with jobs {}",
+            format_time(self.start),
+            format_time(self.end),
+            job_ids.join(", ")
+        )
+    }
+}
+
+fn format_time(time: u64) -> String {
+    DateTime::from_timestamp(time as i64, 0)
+        .map(|time| time.format("%Y-%m-%d %H:%M:%S UTC").to_string())
+        .unwrap_or_else(|| time.to_string())
+}
+
+/// Sweeps over `runs` and returns every stretch where more than `slots` runs overlap and
+/// the new job (the runs without a job id) is one of them. Adjacent stretches with the
+/// same jobs are merged.
+pub fn find_conflicts(runs: &[PlannedRun], slots: usize) -> Vec<ScheduleConflict> {
+    // Ends sort before starts at the same time, back to back runs don't overlap
+    let mut edges: Vec<(u64, bool, Option<u32>)> = runs
+        .iter()
+        .filter(|run| run.start < run.end)
+        .flat_map(|run| [(run.start, true, run.job_id), (run.end, false, run.job_id)])
+        .collect();
+    edges.sort();
+
+    let mut conflicts: Vec<ScheduleConflict> = Vec::new();
+    let mut active: Vec<Option<u32>> = Vec::new();
+    for (i, &(time, starts, job_id)) in edges.iter().enumerate() {
+        match starts {
+            true => active.push(job_id),
+            false => {
+                if let Some(index) = active.iter().position(|active| *active == job_id) {
+                    active.swap_remove(index);
+                }
+            }
+        }
+
+        // Only look at the state once every edge at this time is applied
+        let Some(&(next, ..)) = edges.get(i + 1) else {
+            break;
+        };
+        if next == time || active.len() <= slots || !active.contains(&None) {
+            continue;
+        }
+
+        let mut job_ids: Vec<u32> = active.iter().flatten().copied().collect();
+        job_ids.sort_unstable();
+        job_ids.dedup();
+
+        match conflicts.last_mut() {
+            Some(last) if last.end == time && last.job_ids == job_ids => last.end = next,
+            _ => conflicts.push(ScheduleConflict {
+                start: time,
+                end: next,
+                job_ids,
+            }),
+        }
+    }
+
+    conflicts
+}
+
+/// Lists the first few conflicts in a single line, for error messages.
+pub fn describe_conflicts(conflicts: &[ScheduleConflict]) -> String {
+    let mut description: Vec<String> = conflicts
+        .iter()
+        .take(DESCRIBED_CONFLICTS)
+        .map(ScheduleConflict::to_string)
+        .collect();
+    if conflicts.len() > DESCRIBED_CONFLICTS {
+        description.push(format!(
+            "and {} more",
+            conflicts.len() - DESCRIBED_CONFLICTS
+        ));
+    }
+
+    description.join("; ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2025-01-01 00:00:00 UTC, a Wednesday
+    const NEW_YEAR: u64 = 1_735_689_600;
+    const HOUR: u64 = 3600;
+    const DAY: u64 = 24 * HOUR;
+
+    #[test]
+    fn five_fields_are_accepted() {
+        assert!(parse_cron("0 6 * * *").is_ok());
+    }
+
+    #[test]
+    fn six_fields_are_accepted() {
+        assert!(parse_cron("30 0 6 * * *").is_ok());
+    }
+
+    #[test]
+    fn garbage_is_rejected() {
+        assert!(parse_cron("every day at six").is_err());
+        assert!(parse_cron("").is_err());
+    }
+
+    #[test]
+    fn daily_runs() {
+        let schedule = parse_cron("0 6 * * *").unwrap();
+        let runs = cron_runs_after(&schedule, NEW_YEAR, 3);
+        assert_eq!(
+            runs,
+            vec![
+                NEW_YEAR + 6 * HOUR,
+                NEW_YEAR + DAY + 6 * HOUR,
+                NEW_YEAR + 2 * DAY + 6 * HOUR
+            ]
+        );
+    }
+
+    #[test]
+    fn runs_are_strictly_after() {
+        let schedule = parse_cron("0 * * * *").unwrap();
+        let runs = cron_runs_after(&schedule, NEW_YEAR, 1);
+        assert_eq!(runs, vec![NEW_YEAR + HOUR]);
+    }
+
+    #[test]
+    fn weekdays_skip_weekend() {
+        let schedule = parse_cron("0 0 * * Mon-Fri").unwrap();
+        let runs = cron_runs_after(&schedule, NEW_YEAR, 3);
+        // Thu 2nd, Fri 3rd, then Mon 6th
+        assert_eq!(
+            runs,
+            vec![NEW_YEAR + DAY, NEW_YEAR + 2 * DAY, NEW_YEAR + 5 * DAY]
+        );
+    }
+
+    fn run(job_id: Option<u32>, start: u64, end: u64) -> PlannedRun {
+        PlannedRun { job_id, start, end }
+    }
+
+    #[test]
+    fn runs_between_stops_at_until() {
+        let schedule = parse_cron("0 * * * *").unwrap();
+        let runs = cron_runs_between(&schedule, NEW_YEAR, NEW_YEAR + 3 * HOUR, 10);
+        assert_eq!(runs, vec![NEW_YEAR + HOUR, NEW_YEAR + 2 * HOUR]);
+    }
+
+    #[test]
+    fn no_conflict_within_slots() {
+        let runs = [run(Some(1), 0, 100), run(None, 50, 150)];
+        assert!(find_conflicts(&runs, 2).is_empty());
+    }
+
+    #[test]
+    fn back_to_back_runs_dont_overlap() {
+        let runs = [run(Some(1), 0, 100), run(None, 100, 200)];
+        assert!(find_conflicts(&runs, 1).is_empty());
+    }
+
+    #[test]
+    fn overlap_past_slots() {
+        let runs = [
+            run(Some(1), 0, 100),
+            run(Some(2), 50, 300),
+            run(None, 80, 200),
+        ];
+        assert_eq!(
+            find_conflicts(&runs, 2),
+            vec![ScheduleConflict {
+                start: 80,
+                end: 100,
+                job_ids: vec![1, 2]
+            },]
+        );
+    }
+
+    #[test]
+    fn conflicts_without_new_job_are_ignored() {
+        let runs = [
+            run(Some(1), 0, 100),
+            run(Some(2), 0, 100),
+            run(None, 100, 200),
+        ];
+        assert!(find_conflicts(&runs, 1).is_empty());
+    }
+
+    #[test]
+    fn adjacent_stretches_with_same_jobs_merge() {
+        // Job 1 runs back to back, so the new job overlaps it the whole time
+        let runs = [
+            run(Some(1), 0, 100),
+            run(Some(1), 100, 200),
+            run(Some(2), 150, 160),
+            run(None, 50, 180),
+        ];
+        assert_eq!(
+            find_conflicts(&runs, 1),
+            vec![
+                ScheduleConflict {
+                    start: 50,
+                    end: 150,
+                    job_ids: vec![1]
+                },
+                ScheduleConflict {
+                    start: 150,
+                    end: 160,
+                    job_ids: vec![1, 2]
+                },
+                ScheduleConflict {
+                    start: 160,
+                    end: 180,
+                    job_ids: vec![1]
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn description_lists_first_conflicts() {
+        let conflict = ScheduleConflict {
+            start: NEW_YEAR,
+            end: NEW_YEAR + HOUR,
+            job_ids: vec![1, 2],
+        };
+        assert_eq!(
+            conflict.to_string(),
+            "2025-01-01 00:00:00 UTC to 2025-01-01 01:00:00 UTC with jobs 1, 2"
+        );
+
+        let description = describe_conflicts(&vec![conflict; 5]);
+        assert!(description.ends_with("; and 2 more"));
+    }
+}
@@ -0,0 +1,73 @@
+use crate::job::Log;
+use actix_web::web::Bytes;
+use futures_util::stream::{self, Stream};
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::time::{Duration, timeout};
+
+// Keeps proxies from closing a stream while the job is quiet
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Formats a log as a Server-Sent Event.
+pub fn log_event(log: &Log) -> Bytes {
+    let data = serde_json::to_string(log).expect("Log always serializes");
+    Bytes::from(format!("event: log\ndata: {}\n\n", data))
+}
+
+/// Streams `backlog` first, then every log received until the job is dropped.
+pub fn log_stream(
+    backlog: Vec<Log>,
+    receiver: broadcast::Receiver<Log>,
+) -> impl Stream<Item = Result<Bytes, Infallible>> {
+    let backlog: VecDeque<Log> = backlog.into();
+
+    stream::unfold(
+        (backlog, receiver),
+        |(mut backlog, mut receiver)| async move {
+            if let Some(log) = backlog.pop_front() {
+                return Some((Ok(log_event(&log)), (backlog, receiver)));
+            }
+
+            let event = match timeout(KEEP_ALIVE_INTERVAL, receiver.recv()).await {
+                Ok(Ok(log)) => log_event(&log),
+                Ok(Err(RecvError::Lagged(skipped))) => {
+                    Bytes::from(format!("event: lagged\ndata: {}\n\n", skipped))
+                }
+                Ok(Err(RecvError::Closed)) => return None,
+                Err(..) => Bytes::from_static(b": keep-alive\n\n"),
+            };
+
+            Some((Ok(event), (backlog, receiver)))
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::StreamExt;
+
+    fn log(data: &str) -> Log {
+        Log::new(1_735_689_600, data.to_string())
+    }
+
+    #[test]
+    fn log_event_format() {
+        assert_eq!(
+            log_event(&log("<STDOUT> connected")),
+            "event: log\ndata: {\"timestamp\":1735689600,\"data\":\"<STDOUT> connected\",\"event\":null}\n\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn replays_backlog_then_follows() {
+        let (sender, receiver) = broadcast::channel(16);
+        let stream = log_stream(vec![log("a")], receiver);
+        sender.send(log("b")).unwrap();
+        drop(sender);
+
+        let events: Vec<Bytes> = stream.map(Result::unwrap).collect().await;
+        assert_eq!(events, vec![log_event(&log("a")), log_event(&log("b"))]);
+    }
+}
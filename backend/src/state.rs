@@ -1,21 +1,950 @@
-use crate::job::Job;
-use std::collections::HashMap;
+use crate::config::Config;
+use crate::job::{
+    Job, JobInfo, JobRecord, JobStatus, QueueInfo, RecorderSettings, estimate_queue, restore_jobs,
+};
+use crate::joblog::spawn_log_writer;
+use crate::kiwi::{KiwiClient, KiwiError, KiwiReceiver, ReceiverInfo, ReceiverStatus};
+use crate::schedule::{PlannedRun, ScheduleConflict, find_conflicts};
+use crate::store::JobStore;
+use chrono::Utc;
+use futures_util::future::{join, join_all};
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::io;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
 use tokio::sync::Mutex;
+use tokio::time::{sleep, timeout};
 
 pub type SharedJob = Arc<Mutex<Job>>;
 pub type JobMap = HashMap<u32, SharedJob>;
 pub type SharedJobMap = Arc<Mutex<JobMap>>;
 
+/// What the scheduler should do on a tick.
+#[derive(Default)]
+pub struct StartPlan {
+    pub preempt: Vec<(SharedJob, u32)>, // Running jobs to preempt, and the job id they make room for
+    pub start: Vec<SharedJob>,
+}
+
+// Sort keys of waiting and preemptible jobs, both end in the job id
+type WaitingKey = (Reverse<u8>, bool, Option<u64>, u32);
+type PreemptibleKey = (u8, Reverse<Option<u64>>, u32);
+
+/// The jobs of one receiver, as seen by the scheduler.
+#[derive(Default)]
+struct ReceiverJobs {
+    occupied: usize,
+    started: Vec<u64>, // Start times of the runs holding a slot
+    waiting: Vec<(WaitingKey, SharedJob)>,
+    preemptible: Vec<(PreemptibleKey, SharedJob)>,
+}
+
+/// A configured receiver, with what it reported on the last poll.
+struct ReceiverState {
+    receiver: KiwiReceiver,
+    client: KiwiClient,
+    status: Mutex<Option<ReceiverStatus>>, // None if unknown
+}
+
+impl ReceiverState {
+    /// Asks the Kiwi for its channels and remembers the answer for the scheduler.
+    async fn refresh(&self) -> Result<ReceiverStatus, KiwiError> {
+        let status = self.client.status().await;
+        *self.status.lock().await = status.as_ref().ok().cloned();
+
+        status
+    }
+
+    /// Channels the Kiwi had left on the last poll. Runs in `started` that began after the
+    /// poll aren't in its user count yet, so they are taken off.
+    async fn free_channels(&self, started: &[u64]) -> usize {
+        match &*self.status.lock().await {
+            Some(status) => {
+                let unreported = started
+                    .iter()
+                    .filter(|&&started_at| started_at >= status.checked_at())
+                    .count();
+                status.free_channels().saturating_sub(unreported)
+            }
+            None => usize::MAX,
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct AppState {
     pub jobs: SharedJobMap,
+    pub store: JobStore,
+    pub config: Arc<Config>,
+    receivers: Arc<Vec<ReceiverState>>, // In config order, never empty
+    accepting_jobs: Arc<AtomicBool>,    // False once shutdown has begun
+    making_room: Arc<Mutex<HashSet<u32>>>, // Waiting jobs whose preemption victim is still stopping
+    last_saved: Arc<Mutex<Option<Vec<JobRecord>>>>,
 }
 
 impl Default for AppState {
     fn default() -> Self {
+        Self::new(Config::default())
+    }
+}
+
+impl AppState {
+    pub fn new(config: Config) -> Self {
+        let timeout = Duration::from_secs(config.receiver.timeout);
+        let receivers = config
+            .receivers()
+            .into_iter()
+            .map(|receiver| ReceiverState {
+                client: KiwiClient::new(&receiver.host, receiver.port, timeout),
+                receiver,
+                status: Mutex::new(None),
+            })
+            .collect();
+
         Self {
             jobs: Arc::new(Mutex::new(HashMap::new())),
+            store: JobStore::new(&config.job_store),
+            config: Arc::new(config),
+            receivers: Arc::new(receivers),
+            accepting_jobs: Arc::new(AtomicBool::new(true)),
+            making_room: Arc::new(Mutex::new(HashSet::new())),
+            last_saved: Arc::new(Mutex::new(None)),
         }
     }
+
+    /// Loads saved jobs from the store into the job map. Returns the number of restored jobs.
+    pub async fn restore_jobs(&self) -> io::Result<usize> {
+        let records = self.store.load().await?;
+        let restored = restore_jobs(records, self.jobs.clone()).await;
+
+        let jobs: Vec<SharedJob> = {
+            let map = self.jobs.lock().await;
+            map.values().cloned().collect()
+        };
+        for job in jobs {
+            spawn_log_writer(&*job.lock().await, &self.config.job_logs);
+        }
+
+        self.persist_jobs().await?;
+        Ok(restored)
+    }
+
+    /// Whether new runs may start. Only false once the backend is shutting down.
+    pub fn accepts_jobs(&self) -> bool {
+        self.accepting_jobs.load(Ordering::SeqCst)
+    }
+
+    /// Keeps the API and the scheduler from starting any more runs.
+    pub fn stop_accepting_jobs(&self) {
+        self.accepting_jobs.store(false, Ordering::SeqCst);
+    }
+
+    /// Stops accepting jobs, then gracefully stops every recording while `http` drains the
+    /// server, and saves the jobs. Recorders still running after `shutdown_timeout` are left
+    /// to be killed when they are dropped.
+    pub async fn shut_down(&self, http: impl Future<Output = ()>) {
+        self.stop_accepting_jobs();
+
+        let shutdown_timeout = self.config.shutdown_timeout;
+        let stopped = join(http, self.stop_all_jobs());
+        if timeout(Duration::from_secs(shutdown_timeout), stopped)
+            .await
+            .is_err()
+        {
+            println!(
+                "Error id: e9u3lb7yq2nc5awh. Error info: Shutdown took longer than {} sec, remaining recordings are killed",
+                shutdown_timeout
+            );
+        }
+
+        if let Err(err) = self.persist_jobs().await {
+            println!("Error id: z5s0hn4dk8pt1mcx. Error info: {}", err);
+        }
+    }
+
+    /// Gracefully stops every recording at once, for the backend to exit. Jobs that are
+    /// starting are stopped once their recorder is up, and stops already underway are
+    /// waited for.
+    pub async fn stop_all_jobs(&self) {
+        let jobs: Vec<SharedJob> = {
+            let map = self.jobs.lock().await;
+            map.values().cloned().collect()
+        };
+
+        let stops = jobs.into_iter().map(|job| self.stop_for_shutdown(job));
+        for result in join_all(stops).await {
+            if let Err(err) = result {
+                println!("Error id: k1f6rm0xe8wz3qjd. Error info: {}", err);
+            }
+        }
+    }
+
+    async fn stop_for_shutdown(&self, job: SharedJob) -> io::Result<()> {
+        const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+        loop {
+            match job.lock().await.status() {
+                JobStatus::Starting | JobStatus::Stopping => {}
+                JobStatus::Running => break,
+                _ => return Ok(()),
+            }
+            sleep(POLL_INTERVAL).await;
+        }
+
+        Job::shutdown(job, &self.config).await
+    }
+
+    /// Station names of all recordings currently being written.
+    pub async fn active_recordings(&self) -> Vec<String> {
+        let jobs: Vec<SharedJob> = {
+            let map = self.jobs.lock().await;
+            map.values().cloned().collect()
+        };
+
+        let mut active = Vec::new();
+        for job in jobs {
+            if let Some(station) = job.lock().await.active_recording() {
+                active.push(station.to_string());
+            }
+        }
+
+        active
+    }
+
+    /// Name of the receiver a job with `settings` records from.
+    pub fn receiver_name<'a>(&'a self, settings: &'a RecorderSettings) -> &'a str {
+        settings
+            .receiver()
+            .unwrap_or(&self.receivers[0].receiver.name)
+    }
+
+    fn receiver_state(&self, name: &str) -> Option<&ReceiverState> {
+        self.receivers
+            .iter()
+            .find(|state| state.receiver.name == name)
+    }
+
+    /// Number of jobs holding a slot, per receiver, leaving out the job `except`.
+    async fn occupied_slots(&self, except: Option<u32>) -> HashMap<String, usize> {
+        let jobs: Vec<SharedJob> = {
+            let map = self.jobs.lock().await;
+            map.values().cloned().collect()
+        };
+
+        let mut occupied = HashMap::new();
+        for job in jobs {
+            let job = job.lock().await;
+            if job.occupies_slot() && Some(job.id()) != except {
+                let receiver = self.receiver_name(job.settings()).to_string();
+                *occupied.entry(receiver).or_default() += 1;
+            }
+        }
+
+        occupied
+    }
+
+    /// Whether a job on `receiver` fits in the slots, both in total and on the receiver.
+    pub async fn slot_free(&self, receiver: &str) -> bool {
+        self.slot_free_except(receiver, None).await
+    }
+
+    /// Whether the job `job_id` still fits in the slots after moving to `receiver`.
+    pub async fn slot_free_for_move(&self, receiver: &str, job_id: u32) -> bool {
+        self.slot_free_except(receiver, Some(job_id)).await
+    }
+
+    async fn slot_free_except(&self, receiver: &str, except: Option<u32>) -> bool {
+        let Some(state) = self.receiver_state(receiver) else {
+            return false;
+        };
+
+        let occupied = self.occupied_slots(except).await;
+        occupied.values().sum::<usize>() < self.config.max_job_slots
+            && occupied.get(receiver).copied().unwrap_or_default() < state.receiver.channels
+    }
+
+    /// Polls every receiver, returning the errors of the ones that couldn't be asked.
+    pub async fn refresh_receivers(&self) -> Vec<KiwiError> {
+        let mut errors = Vec::new();
+        for state in self.receivers.iter() {
+            if let Err(err) = state.refresh().await {
+                errors.push(err);
+            }
+        }
+
+        errors
+    }
+
+    /// Whether `receiver` has a channel left for a new recording. If the Kiwi can't be
+    /// asked, the recorder is left to find out when it connects.
+    pub async fn channel_free(&self, receiver: &str) -> bool {
+        if !self.config.receiver.check_channels {
+            return true;
+        }
+        let Some(state) = self.receiver_state(receiver) else {
+            return false;
+        };
+
+        match state.refresh().await {
+            Ok(status) => status.free_channels() > 0,
+            Err(err) => {
+                println!("Error id: h8v2cz6nt1qy4wkb. Error info: {}", err);
+                true
+            }
+        }
+    }
+
+    /// Asks every receiver for its channels.
+    pub async fn receiver_infos(&self) -> Vec<ReceiverInfo> {
+        let occupied = self.occupied_slots(None).await;
+
+        let mut infos = Vec::with_capacity(self.receivers.len());
+        for state in self.receivers.iter() {
+            let used = occupied.get(&state.receiver.name).copied();
+            let status = state.refresh().await;
+            infos.push(ReceiverInfo::new(
+                &state.receiver,
+                used.unwrap_or_default(),
+                status,
+            ));
+        }
+
+        infos
+    }
+
+    /// Asks the first receiver for its channels.
+    pub async fn refresh_first_receiver(&self) -> Result<ReceiverStatus, KiwiError> {
+        self.receivers[0].refresh().await
+    }
+
+    /// Asks one receiver for its channels, None if there's no receiver called `name`.
+    pub async fn receiver_info(&self, name: &str) -> Option<ReceiverInfo> {
+        let state = self.receiver_state(name)?;
+        let used = self.occupied_slots(None).await.get(name).copied();
+        let status = state.refresh().await;
+
+        Some(ReceiverInfo::new(
+            &state.receiver,
+            used.unwrap_or_default(),
+            status,
+        ))
+    }
+
+    /// Jobs the scheduler should start now, and running jobs to preempt to make room for them.
+    /// Each receiver is planned on its own. Waiting jobs go by priority, then queued jobs in
+    /// the order they were queued, then scheduled jobs by due time. A job that is already
+    /// having room made for it waits for that slot instead of preempting another job.
+    pub async fn plan_starts(&self) -> StartPlan {
+        let jobs: Vec<SharedJob> = {
+            let map = self.jobs.lock().await;
+            map.values().cloned().collect()
+        };
+        let making_room: Vec<u32> = self.making_room.lock().await.iter().copied().collect();
+
+        let mut occupied = 0;
+        let mut receivers: HashMap<String, ReceiverJobs> = HashMap::new();
+        for shared_job in jobs {
+            let job = shared_job.lock().await;
+            let priority = job.settings().priority();
+            let receiver = receivers
+                .entry(self.receiver_name(job.settings()).to_string())
+                .or_default();
+            if job.occupies_slot() {
+                occupied += 1;
+                receiver.occupied += 1;
+                receiver.started.push(job.started_at().unwrap_or_default());
+                if job.status() == JobStatus::Running {
+                    // The newest run of the lowest priority loses the least when preempted
+                    let key = (priority, Reverse(job.started_at()), job.id());
+                    receiver.preemptible.push((key, shared_job.clone()));
+                }
+            } else if job.is_waiting_to_start() {
+                let queued = job.status() == JobStatus::Queued;
+                let key = (Reverse(priority), !queued, job.next_run_start(), job.id());
+                receiver.waiting.push((key, shared_job.clone()));
+            }
+        }
+
+        let mut free_total = self.config.max_job_slots.saturating_sub(occupied);
+        let mut plan = StartPlan::default();
+        for state in self.receivers.iter() {
+            let Some(mut jobs) = receivers.remove(&state.receiver.name) else {
+                continue;
+            };
+            jobs.waiting.sort_by_key(|(key, _)| *key);
+            jobs.preemptible.sort_by_key(|(key, _)| *key);
+
+            let free_channels = match self.config.receiver.check_channels {
+                true => state.free_channels(&jobs.started).await,
+                false => usize::MAX,
+            };
+            let free = state
+                .receiver
+                .channels
+                .saturating_sub(jobs.occupied)
+                .min(free_channels)
+                .min(free_total);
+
+            let (start, preempt) = plan_slots(
+                &jobs
+                    .waiting
+                    .iter()
+                    .map(|((Reverse(priority), .., job_id), _)| (*job_id, *priority))
+                    .collect::<Vec<_>>(),
+                &jobs
+                    .preemptible
+                    .iter()
+                    .map(|((priority, _, job_id), _)| (*job_id, *priority))
+                    .collect::<Vec<_>>(),
+                free,
+                &making_room,
+            );
+            free_total -= start.len() - preempt.len();
+
+            plan.preempt.extend(preempt.into_iter().map(|(i, by)| {
+                let (.., by_job_id) = jobs.waiting[by].0;
+                (jobs.preemptible[i].1.clone(), by_job_id)
+            }));
+            plan.start
+                .extend(start.into_iter().map(|i| jobs.waiting[i].1.clone()));
+        }
+
+        plan
+    }
+
+    /// Preempts `job` for the waiting job `by_job_id` in the background, as stopping can take
+    /// up to `stop_grace_period`. Until it is done, `by_job_id` won't preempt anything else.
+    pub async fn preempt_in_background(&self, job: SharedJob, by_job_id: u32) {
+        self.making_room.lock().await.insert(by_job_id);
+
+        let state = self.clone();
+        tokio::spawn(async move {
+            if let Err(err) = Job::preempt(job, by_job_id, &state.config).await {
+                println!("Error id: w4j8ze1nq6tk2vdr. Error info: {}", err);
+            }
+            state.making_room.lock().await.remove(&by_job_id);
+        });
+    }
+
+    /// The running job on `receiver` with the lowest priority, if it is below `priority`.
+    pub async fn preemption_victim(&self, priority: u8, receiver: &str) -> Option<SharedJob> {
+        let jobs: Vec<SharedJob> = {
+            let map = self.jobs.lock().await;
+            map.values().cloned().collect()
+        };
+
+        let mut victims = Vec::new();
+        for shared_job in jobs {
+            let job = shared_job.lock().await;
+            let job_priority = job.settings().priority();
+            if job.status() == JobStatus::Running
+                && job_priority < priority
+                && self.receiver_name(job.settings()) == receiver
+            {
+                victims.push((
+                    (job_priority, Reverse(job.started_at())),
+                    shared_job.clone(),
+                ));
+            }
+        }
+
+        victims
+            .into_iter()
+            .min_by_key(|(key, _)| *key)
+            .map(|(_, job)| job)
+    }
+
+    /// Queue positions and estimated starts of all queued jobs, by job id. Every receiver
+    /// has its own queue.
+    pub async fn queue_info(&self) -> HashMap<u32, QueueInfo> {
+        let jobs: Vec<SharedJob> = {
+            let map = self.jobs.lock().await;
+            map.values().cloned().collect()
+        };
+
+        let now = Utc::now().timestamp() as u64;
+        let mut occupied_total = 0;
+        let mut receivers: HashMap<String, (Vec<Option<u64>>, Vec<_>)> = HashMap::new();
+        for job in jobs {
+            let job = job.lock().await;
+            let (slots, queued) = receivers
+                .entry(self.receiver_name(job.settings()).to_string())
+                .or_default();
+            if job.occupies_slot() {
+                slots.push(job.slot_free_at());
+                occupied_total += 1;
+            } else if job.status() == JobStatus::Queued {
+                let key = (
+                    Reverse(job.settings().priority()),
+                    job.next_run_start(),
+                    job.id(),
+                );
+                queued.push((key, job.settings().duration()));
+            }
+        }
+
+        let mut queue = HashMap::new();
+        for (receiver, (mut slots, mut queued)) in receivers {
+            let channels = self
+                .receiver_state(&receiver)
+                .map_or(0, |state| state.receiver.channels);
+            queued.sort_by_key(|(key, _)| *key);
+            // Slots held on other receivers count against the global limit as well
+            let occupied_elsewhere = occupied_total - slots.len();
+            let capacity =
+                channels.min(self.config.max_job_slots.saturating_sub(occupied_elsewhere));
+            slots.resize(capacity.max(slots.len()), Some(now));
+
+            let durations: Vec<u16> = queued.iter().map(|(_, duration)| *duration).collect();
+            queue.extend(
+                queued
+                    .iter()
+                    .map(|((.., job_id), _)| *job_id)
+                    .zip(estimate_queue(slots, &durations, now)),
+            );
+        }
+
+        queue
+    }
+
+    /// Predicts where a new job with `settings`, first running at `first_run`, would push
+    /// usage of its receiver or of all receivers together past the available slots within
+    /// the configured horizon. The runs of the job `replacing`, whose settings these would
+    /// replace, are left out.
+    pub async fn schedule_conflicts(
+        &self,
+        settings: &RecorderSettings,
+        first_run: u64,
+        replacing: Option<u32>,
+    ) -> Vec<ScheduleConflict> {
+        let horizon = self.config.conflict_horizon_days as u64 * 24 * 60 * 60;
+        let receiver = self.receiver_name(settings);
+        let Some(state) = self.receiver_state(receiver).filter(|_| horizon > 0) else {
+            return Vec::new();
+        };
+
+        let jobs: Vec<SharedJob> = {
+            let map = self.jobs.lock().await;
+            map.values().cloned().collect()
+        };
+
+        let now = Utc::now().timestamp() as u64;
+        let until = now + horizon;
+        let new_runs: Vec<PlannedRun> = settings
+            .planned_runs(first_run, until)
+            .into_iter()
+            .map(|(start, end)| PlannedRun {
+                job_id: None,
+                start,
+                end,
+            })
+            .collect();
+        let mut receiver_runs = new_runs.clone();
+        let mut all_runs = new_runs;
+        for job in jobs {
+            let job = job.lock().await;
+            if Some(job.id()) == replacing {
+                continue;
+            }
+            let runs = job
+                .planned_runs(now, until)
+                .into_iter()
+                .map(|(start, end)| PlannedRun {
+                    job_id: Some(job.id()),
+                    start,
+                    end,
+                });
+            if self.receiver_name(job.settings()) == receiver {
+                receiver_runs.extend(runs.clone());
+            }
+            all_runs.extend(runs);
+        }
+
+        let channels = state.receiver.channels.min(self.config.max_job_slots);
+        let mut conflicts = find_conflicts(&receiver_runs, channels);
+        conflicts.extend(find_conflicts(&all_runs, self.config.max_job_slots));
+        conflicts.sort_by_key(|conflict| (conflict.start, conflict.end));
+        conflicts.dedup();
+
+        conflicts
+    }
+
+    /// The status of a job, including its place in the queue.
+    pub async fn job_info(&self, job: &SharedJob) -> JobInfo {
+        let queue = self.queue_info().await;
+        let job = job.lock().await;
+
+        JobInfo::from(&*job).with_queue(queue.get(&job.id()).copied())
+    }
+
+    pub async fn snapshot_jobs(&self) -> Vec<JobRecord> {
+        let jobs: Vec<SharedJob> = {
+            let map = self.jobs.lock().await;
+            map.values().cloned().collect()
+        };
+
+        let mut records = Vec::with_capacity(jobs.len());
+        for job in jobs {
+            let job = job.lock().await;
+            records.push(JobRecord::from(&*job));
+        }
+        records.sort_by_key(|record| record.job_id());
+
+        records
+    }
+
+    /// Saves the job map to the store, skipping the write if nothing changed since the last save.
+    pub async fn persist_jobs(&self) -> io::Result<()> {
+        let records = self.snapshot_jobs().await;
+
+        let mut last_saved = self.last_saved.lock().await;
+        if last_saved.as_ref() == Some(&records) {
+            return Ok(());
+        }
+
+        self.store.save(&records).await?;
+        *last_saved = Some(records);
+
+        Ok(())
+    }
+}
+
+/// Picks which waiting jobs to start, and which running jobs to preempt for them. Both lists
+/// hold `(job_id, priority)`, `waiting` sorted by importance and `preemptible` by how readily
+/// a job gives up its slot. Waiting jobs in `making_room` already have a job stopping for
+/// them and don't preempt another. Returns the indices of the waiting jobs to start, and
+/// pairs of running job index and the waiting job index it is preempted for.
+fn plan_slots(
+    waiting: &[(u32, u8)],
+    preemptible: &[(u32, u8)],
+    free: usize,
+    making_room: &[u32],
+) -> (Vec<usize>, Vec<(usize, usize)>) {
+    let mut start: Vec<usize> = (0..waiting.len().min(free)).collect();
+    let mut preempt = Vec::new();
+
+    let mut victims = preemptible.iter().enumerate();
+    for (i, &(job_id, priority)) in waiting.iter().enumerate().skip(free) {
+        if making_room.contains(&job_id) {
+            continue;
+        }
+        match victims.next() {
+            Some((victim, &(_, victim_priority))) if victim_priority < priority => {
+                preempt.push((victim, i));
+                start.push(i);
+            }
+            _ => break,
+        }
+    }
+
+    (start, preempt)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::RecorderConfig;
+    use crate::job::{RecordingType, create_job, generate_uid};
+    use crate::kiwi::ReceiverConfig;
+    use std::path::PathBuf;
+
+    fn two_receivers() -> AppState {
+        two_receivers_with(1, Config::default().max_job_slots)
+    }
+
+    fn two_receivers_with(channels: usize, max_job_slots: usize) -> AppState {
+        let receiver = |name: &str| KiwiReceiver {
+            name: name.into(),
+            host: "127.0.0.1".into(),
+            port: 8073,
+            password: None,
+            tlimit_password: None,
+            channels,
+        };
+
+        AppState::new(Config {
+            receivers: vec![receiver("roof"), receiver("mast")],
+            max_job_slots,
+            receiver: ReceiverConfig {
+                check_channels: false,
+                ..ReceiverConfig::default()
+            },
+            ..Config::default()
+        })
+    }
+
+    async fn queue_job(state: &AppState, receiver: &str) {
+        let settings = RecorderSettings::new(RecordingType::PNG, 10_000_000, 5, 10, None)
+            .with_receiver(receiver);
+        create_job(settings, state.jobs.clone())
+            .await
+            .lock()
+            .await
+            .queue();
+    }
+
+    #[tokio::test]
+    async fn receivers_have_their_own_slots() {
+        let state = two_receivers();
+        queue_job(&state, "roof").await;
+        queue_job(&state, "mast").await;
+
+        assert_eq!(state.plan_starts().await.start.len(), 2);
+        assert!(state.slot_free("roof").await);
+        assert!(!state.slot_free("attic").await);
+    }
+
+    #[tokio::test]
+    async fn full_receiver_holds_back_its_jobs() {
+        let state = two_receivers();
+        queue_job(&state, "roof").await;
+        queue_job(&state, "roof").await;
+
+        assert_eq!(state.plan_starts().await.start.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn conflicts_count_all_receivers_against_max_job_slots() {
+        let state = two_receivers_with(2, 3);
+        queue_job(&state, "roof").await;
+        queue_job(&state, "mast").await;
+
+        let now = Utc::now().timestamp() as u64;
+        let settings = RecorderSettings::new(RecordingType::PNG, 10_000_000, 5, 10, None)
+            .with_receiver("mast");
+        assert!(
+            state
+                .schedule_conflicts(&settings, now, None)
+                .await
+                .is_empty()
+        );
+
+        queue_job(&state, "roof").await;
+        let conflicts = state.schedule_conflicts(&settings, now, None).await;
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].job_ids.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn updated_job_doesnt_conflict_with_itself() {
+        let state = two_receivers();
+        queue_job(&state, "roof").await;
+        let job_id = *state.jobs.lock().await.keys().next().unwrap();
+
+        let now = Utc::now().timestamp() as u64;
+        let settings = RecorderSettings::new(RecordingType::PNG, 10_000_000, 5, 10, None)
+            .with_receiver("roof");
+        assert!(
+            !state
+                .schedule_conflicts(&settings, now, None)
+                .await
+                .is_empty()
+        );
+        assert!(
+            state
+                .schedule_conflicts(&settings, now, Some(job_id))
+                .await
+                .is_empty()
+        );
+    }
+
+    // A kiwirecorder.py stand in that notes the SIGINT and exits
+    const STOPS_ON_SIGINT: &str =
+        "trap 'touch interrupted; exit 0' INT\nwhile :; do sleep 0.05; done\n";
+    // One that has to be killed
+    const IGNORES_SIGINT: &str = "trap '' INT\nwhile :; do sleep 0.05; done\n";
+
+    /// A state whose recorder is the shell `script`, with a one second grace period.
+    fn stub_recorder_state(name: &str, script: &str) -> (AppState, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("kiwi-{}-{}", name, generate_uid()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("kiwirecorder.py"), script).unwrap();
+
+        let state = AppState::new(Config {
+            shutdown_timeout: 5,
+            job_store: dir.join("jobs.json"),
+            recorder: RecorderConfig {
+                python: "sh".into(),
+                kiwiclient_dir: dir.clone(),
+                output_dir: dir.clone(),
+                stop_grace_period: 1,
+                ..RecorderConfig::default()
+            },
+            receiver: ReceiverConfig {
+                check_channels: false,
+                ..ReceiverConfig::default()
+            },
+            ..Config::default()
+        });
+
+        (state, dir)
+    }
+
+    #[tokio::test]
+    async fn waiting_job_preempts_only_once() {
+        let (state, dir) = stub_recorder_state("preempt", IGNORES_SIGINT);
+        for _ in 0..state.config.max_job_slots {
+            let settings = RecorderSettings::new(RecordingType::PNG, 10_000_000, 5, 0, None);
+            let job = create_job(settings, state.jobs.clone()).await;
+            Job::start(job, &state.config).await.unwrap();
+        }
+        // Give the shells time to ignore SIGINT
+        sleep(Duration::from_millis(200)).await;
+        let settings =
+            RecorderSettings::new(RecordingType::PNG, 10_000_000, 5, 0, None).with_priority(5);
+        let waiting = create_job(settings, state.jobs.clone()).await;
+        waiting.lock().await.queue();
+        let waiting_id = waiting.lock().await.id();
+
+        let plan = state.plan_starts().await;
+        assert_eq!(plan.preempt.len(), 1);
+        for (job, by_job_id) in plan.preempt {
+            state.preempt_in_background(job, by_job_id).await;
+        }
+
+        // The victim is stopping for the whole grace period
+        sleep(Duration::from_millis(200)).await;
+        let plan = state.plan_starts().await;
+        assert!(plan.preempt.is_empty());
+        assert!(plan.start.is_empty());
+
+        sleep(Duration::from_millis(1300)).await;
+        let plan = state.plan_starts().await;
+        assert!(plan.preempt.is_empty());
+        assert_eq!(plan.start.len(), 1);
+        assert_eq!(plan.start[0].lock().await.id(), waiting_id);
+
+        state.shut_down(async {}).await;
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn shutdown_waits_for_stops_underway() {
+        let (state, dir) = stub_recorder_state("stopping", IGNORES_SIGINT);
+        let settings = RecorderSettings::new(RecordingType::PNG, 10_000_000, 5, 0, None);
+        let job = create_job(settings, state.jobs.clone()).await;
+        Job::start(job.clone(), &state.config).await.unwrap();
+        // Give the shell time to ignore SIGINT
+        sleep(Duration::from_millis(200)).await;
+        state.preempt_in_background(job.clone(), 99).await;
+        sleep(Duration::from_millis(200)).await;
+        assert_eq!(job.lock().await.status(), JobStatus::Stopping);
+
+        state.shut_down(async {}).await;
+
+        let records = state.store.load().await.unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].status(), JobStatus::Queued);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn restart_is_not_picked_up_by_the_scheduler() {
+        let (state, dir) = stub_recorder_state("restart", STOPS_ON_SIGINT);
+        // Due again a second after it started, so the scheduler would take it once stopped
+        let settings = RecorderSettings::new(RecordingType::PNG, 10_000_000, 5, 0, Some(1));
+        let job = create_job(settings, state.jobs.clone()).await;
+        Job::start(job.clone(), &state.config).await.unwrap();
+        sleep(Duration::from_millis(1200)).await;
+
+        let new_settings = RecorderSettings::new(RecordingType::PNG, 7_100_000, 5, 0, Some(1));
+        let restart = Job::restart_with_settings(job.clone(), new_settings.clone(), &state.config);
+        let ticks = async {
+            let mut planned = 0;
+            for _ in 0..50 {
+                planned += state.plan_starts().await.start.len();
+                sleep(Duration::from_millis(10)).await;
+            }
+            planned
+        };
+        let (restarted, planned) = join(restart, ticks).await;
+
+        restarted.unwrap();
+        assert_eq!(planned, 0);
+        assert!(dir.join("interrupted").exists());
+        let job = job.lock().await;
+        assert_eq!(job.status(), JobStatus::Running);
+        assert_eq!(job.settings(), &new_settings);
+        drop(job);
+
+        state.shut_down(async {}).await;
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn shutdown_sends_sigint_to_recorders() {
+        let (state, dir) = stub_recorder_state("shutdown", STOPS_ON_SIGINT);
+        let settings = RecorderSettings::new(RecordingType::PNG, 10_000_000, 5, 0, None);
+        let job = create_job(settings, state.jobs.clone()).await;
+        Job::start(job.clone(), &state.config).await.unwrap();
+        // Give the shell time to set up its trap
+        sleep(Duration::from_millis(200)).await;
+
+        let started = std::time::Instant::now();
+        state.shut_down(async {}).await;
+
+        assert!(started.elapsed() < Duration::from_secs(2));
+        assert!(dir.join("interrupted").exists());
+        let job = job.lock().await;
+        assert_ne!(job.status(), JobStatus::Running);
+        assert!(
+            job.logs()
+                .iter()
+                .any(|log| log.data().starts_with("<Shutdown>"))
+        );
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn shutdown_leaves_queued_jobs_queued() {
+        let state = two_receivers();
+        queue_job(&state, "roof").await;
+
+        state.stop_accepting_jobs();
+        state.stop_all_jobs().await;
+
+        assert!(!state.accepts_jobs());
+        for job in state.jobs.lock().await.values() {
+            assert_eq!(job.lock().await.status(), JobStatus::Queued);
+        }
+    }
+
+    #[test]
+    fn unnamed_receiver_is_the_first() {
+        let state = two_receivers();
+        let settings = RecorderSettings::new(RecordingType::PNG, 10_000_000, 5, 10, None);
+        assert_eq!(state.receiver_name(&settings), "roof");
+    }
+
+    #[test]
+    fn free_slots_start_in_order() {
+        let (start, preempt) = plan_slots(&[(1, 0), (2, 0), (3, 0)], &[], 2, &[]);
+        assert_eq!(start, vec![0, 1]);
+        assert!(preempt.is_empty());
+    }
+
+    #[test]
+    fn higher_priority_preempts_lower() {
+        let (start, preempt) = plan_slots(&[(1, 5), (2, 5), (3, 0)], &[(7, 1), (8, 9)], 0, &[]);
+        assert_eq!(start, vec![0]);
+        assert_eq!(preempt, vec![(0, 0)]);
+    }
+
+    #[test]
+    fn equal_priority_doesnt_preempt() {
+        let (start, preempt) = plan_slots(&[(1, 3)], &[(7, 3)], 0, &[]);
+        assert!(start.is_empty());
+        assert!(preempt.is_empty());
+    }
+
+    #[test]
+    fn free_slot_used_before_preempting() {
+        let (start, preempt) = plan_slots(&[(1, 9), (2, 9)], &[(7, 0)], 1, &[]);
+        assert_eq!(start, vec![0, 1]);
+        assert_eq!(preempt, vec![(0, 1)]);
+    }
 }
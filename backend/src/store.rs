@@ -0,0 +1,86 @@
+use crate::job::JobRecord;
+use std::io;
+use std::path::{Path, PathBuf};
+use tokio::fs;
+
+pub const DEFAULT_JOB_STORE_PATH: &str = "/var/recorder/jobs.json";
+
+#[derive(Debug, Clone)]
+pub struct JobStore {
+    path: PathBuf,
+}
+
+impl Default for JobStore {
+    fn default() -> Self {
+        Self::new(DEFAULT_JOB_STORE_PATH)
+    }
+}
+
+impl JobStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Reads all saved jobs. A missing file is treated as an empty store.
+    pub async fn load(&self) -> io::Result<Vec<JobRecord>> {
+        let data = match fs::read(&self.path).await {
+            Ok(data) => data,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err),
+        };
+
+        serde_json::from_slice(&data).map_err(io::Error::other)
+    }
+
+    /// Writes all jobs to disk. The file is written next to the target and then renamed,
+    /// so a power loss mid-write never leaves a half written store behind.
+    pub async fn save(&self, records: &[JobRecord]) -> io::Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+
+        let data = serde_json::to_vec_pretty(records).map_err(io::Error::other)?;
+
+        let tmp_path = self.path.with_extension("json.tmp");
+        fs::write(&tmp_path, data).await?;
+        fs::rename(&tmp_path, &self.path).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::job::*;
+
+    fn test_store(name: &str) -> JobStore {
+        let dir = std::env::temp_dir().join(format!("kiwi-store-{}-{}", name, generate_uid()));
+        JobStore::new(dir.join("jobs.json"))
+    }
+
+    #[tokio::test]
+    async fn load_missing_file_is_empty() {
+        let store = test_store("missing");
+        assert!(store.load().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn save_then_load_round_trip() {
+        let store = test_store("round-trip");
+        let settings = RecorderSettings::new(RecordingType::PNG, 10_000_000, 5, 10, Some(3600));
+        let records = vec![
+            JobRecord::from(&Job::new(0, settings.clone())),
+            JobRecord::from(&Job::new(1, settings)),
+        ];
+
+        store.save(&records).await.unwrap();
+        let loaded = store.load().await.unwrap();
+
+        assert_eq!(loaded, records);
+
+        let _ = fs::remove_dir_all(store.path().parent().unwrap()).await;
+    }
+}